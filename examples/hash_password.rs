@@ -0,0 +1,16 @@
+use std::io::stdin;
+
+use music_uploader_server::password_utils::hash_password;
+
+/// reads a password from stdin and prints the line to paste into Secrets.toml
+fn main() {
+    println!("enter the password to hash:");
+    let mut password = String::new();
+    stdin()
+        .read_line(&mut password)
+        .expect("failed to read password from stdin");
+    match hash_password(&password) {
+        Ok(password_hash) => println!("password_hash=\"{password_hash}\""),
+        Err(e) => println!("failure: {e}"),
+    }
+}
@@ -0,0 +1,107 @@
+use rocket::{
+    get, http, post,
+    request::{self, FromRequest},
+    Request, State,
+};
+
+use crate::{
+    alias::{add_alias, list_aliases, remove_alias},
+    authenticated::Admin,
+    config::server_config::ServerConfig,
+    data::metrics::Metrics,
+    model::{AliasResponse, HeaderError, MusicUploaderError},
+    rocket_utils::{get_header_value, get_optional_header_value},
+};
+
+/// canonical is only needed to add an alias, artist limits an album alias to one artist.
+pub struct AliasHeaders {
+    kind: String,
+    alias: String,
+    canonical: Option<String>,
+    artist: Option<String>,
+}
+
+#[get("/aliases")]
+pub async fn aliases(
+    auth: Admin,
+    server_config: &State<ServerConfig>,
+) -> Result<AliasResponse, MusicUploaderError> {
+    let response = list_aliases(server_config)?;
+    metric(&server_config.server_db_dir, "aliases", &auth.username);
+    Ok(response)
+}
+
+#[post("/addalias")]
+pub async fn create_alias(
+    auth: Admin,
+    server_config: &State<ServerConfig>,
+    headers: AliasHeaders,
+) -> Result<String, MusicUploaderError> {
+    let canonical = headers
+        .canonical
+        .as_ref()
+        .ok_or(MusicUploaderError::ConstraintViolation(
+            "adding an alias needs the canonical header".to_string(),
+        ))?;
+    add_alias(
+        server_config,
+        &headers.kind,
+        headers.artist.as_deref(),
+        &headers.alias,
+        canonical,
+        &auth.username,
+    )?;
+    metric(&server_config.server_db_dir, "addAlias", &auth.username);
+    Ok(format!(
+        "{} {} is now filed as {canonical}",
+        headers.kind, headers.alias
+    ))
+}
+
+#[post("/removealias")]
+pub async fn delete_alias(
+    auth: Admin,
+    server_config: &State<ServerConfig>,
+    headers: AliasHeaders,
+) -> Result<String, MusicUploaderError> {
+    remove_alias(
+        server_config,
+        &headers.kind,
+        headers.artist.as_deref(),
+        &headers.alias,
+        &auth.username,
+    )?;
+    metric(&server_config.server_db_dir, "removeAlias", &auth.username);
+    Ok(format!(
+        "removed the {} alias {}",
+        headers.kind, headers.alias
+    ))
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AliasHeaders {
+    type Error = HeaderError;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match Self::from_request_inner(req).await {
+            Ok(a) => request::Outcome::Success(a),
+            Err(e) => request::Outcome::Error((http::Status::BadRequest, e)),
+        }
+    }
+}
+
+impl<'r> AliasHeaders {
+    async fn from_request_inner(req: &'r Request<'_>) -> Result<Self, HeaderError> {
+        let headers = req.headers();
+        Ok(Self {
+            kind: get_header_value(headers, "kind")?,
+            alias: get_header_value(headers, "alias")?,
+            canonical: get_optional_header_value(headers, "canonical")?,
+            artist: get_optional_header_value(headers, "artist")?,
+        })
+    }
+}
+
+fn metric(db_path: &String, route: &str, user: &String) {
+    let metrics = Metrics::new(db_path);
+    let _ = metrics.note_route(&route.to_string(), user);
+}
@@ -0,0 +1,137 @@
+use rocket::{
+    delete, http, post,
+    request::{self, FromRequest},
+    Request, State,
+};
+
+use crate::{
+    authenticated::{Admin, Authenticated, Authenticator, PasswordAuthenticated},
+    config::server_config::ServerConfig,
+    data::{metrics::Metrics, operational_data::OperationalData},
+    model::{ApiTokenResponse, HeaderError, MusicUploaderError},
+    password_utils::{generate_secret_token, hash_secret_token},
+    rocket_utils::get_header_value,
+    time_utils::get_now_timestamp,
+};
+
+const ONE_DAY_IN_SECONDS: i64 = 60 * 60 * 24;
+
+pub struct RevokeApiTokenHeaders {
+    token: String,
+}
+
+pub struct RevokeUserApiTokensHeaders {
+    user: String,
+}
+
+/// exchanges the caller's credentials for a bearer token so clients do not need to hold onto the password.
+/// a token can't be used to get another, or a leaked one could keep itself alive forever.
+#[post("/token")]
+pub async fn create_api_token(
+    auth: PasswordAuthenticated,
+    authenticator: &State<Authenticator>,
+    server_config: &State<ServerConfig>,
+) -> Result<ApiTokenResponse, MusicUploaderError> {
+    println!("{} is creating an api token", auth.username);
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    if !authenticator.has_user(&operational_data, &auth.username) {
+        // tokens look up the user's role from the local users, so they would never work for plex users.
+        return Err(MusicUploaderError::ConstraintViolation(
+            "api tokens are only available to local users".to_string(),
+        ));
+    }
+    let token = generate_secret_token();
+    let expires_at =
+        get_now_timestamp() + server_config.api_token_lifetime_days as i64 * ONE_DAY_IN_SECONDS;
+    let api_token = operational_data
+        .create_api_token(&hash_secret_token(&token), &auth.username, expires_at)
+        .ok_or(MusicUploaderError::InternalServerError(
+            "Failed to create api token".to_string(),
+        ))?;
+    metric(
+        &server_config.server_db_dir,
+        "createApiToken",
+        &auth.username,
+    );
+    Ok(ApiTokenResponse {
+        token,
+        expires: api_token.expires_at,
+    })
+}
+
+#[delete("/token")]
+pub async fn revoke_api_token(
+    auth: Authenticated,
+    server_config: &State<ServerConfig>,
+    headers: RevokeApiTokenHeaders,
+) -> Result<String, MusicUploaderError> {
+    println!("{} is revoking an api token", auth.username);
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let revoked = operational_data
+        .revoke_api_token(&hash_secret_token(&headers.token), &auth.username)
+        .ok_or(MusicUploaderError::InternalServerError(
+            "Failed to revoke api token".to_string(),
+        ))?;
+    metric(
+        &server_config.server_db_dir,
+        "revokeApiToken",
+        &auth.username,
+    );
+    match revoked {
+        0 => Err(MusicUploaderError::ConstraintViolation(
+            "no matching api token".to_string(),
+        )),
+        _ => Ok("revoked api token".to_string()),
+    }
+}
+
+#[post("/revokeusertokens")]
+pub async fn revoke_user_api_tokens(
+    auth: Admin,
+    server_config: &State<ServerConfig>,
+    headers: RevokeUserApiTokensHeaders,
+) -> Result<String, MusicUploaderError> {
+    println!(
+        "{} is revoking all api tokens of {}",
+        auth.username, headers.user
+    );
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let revoked = operational_data
+        .revoke_user_api_tokens(&headers.user)
+        .ok_or(MusicUploaderError::InternalServerError(
+            "Failed to revoke api tokens".to_string(),
+        ))?;
+    metric(
+        &server_config.server_db_dir,
+        "revokeUserApiTokens",
+        &auth.username,
+    );
+    Ok(format!("revoked {revoked} api tokens of {}", headers.user))
+}
+
+fn metric(db_path: &String, route: &str, user: &String) {
+    let metrics = Metrics::new(db_path);
+    let _ = metrics.note_route(&route.to_string(), user);
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RevokeApiTokenHeaders {
+    type Error = HeaderError;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match get_header_value(req.headers(), "token") {
+            Ok(token) => request::Outcome::Success(Self { token }),
+            Err(e) => request::Outcome::Error((http::Status::BadRequest, e)),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RevokeUserApiTokensHeaders {
+    type Error = HeaderError;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match get_header_value(req.headers(), "user") {
+            Ok(user) => request::Outcome::Success(Self { user }),
+            Err(e) => request::Outcome::Error((http::Status::BadRequest, e)),
+        }
+    }
+}
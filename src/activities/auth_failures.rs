@@ -0,0 +1,39 @@
+use rocket::{get, State};
+
+use crate::{
+    authenticated::Admin,
+    config::server_config::ServerConfig,
+    data::{metrics::Metrics, operational_data::OperationalData},
+    model::{AuthFailuresResponse, ListedAuthFailure, MusicUploaderError},
+};
+
+const MAX_LISTED_FAILURES: u32 = 200;
+
+#[get("/authfailures")]
+pub async fn auth_failures(
+    auth: Admin,
+    server_config: &State<ServerConfig>,
+) -> Result<AuthFailuresResponse, MusicUploaderError> {
+    println!("{} is reviewing auth failures", auth.username);
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let failures = operational_data
+        .get_recent_auth_failures(MAX_LISTED_FAILURES)
+        .ok_or(MusicUploaderError::InternalServerError(
+            "Failed to get auth failures".to_string(),
+        ))?
+        .into_iter()
+        .map(|item| ListedAuthFailure {
+            username: item.username,
+            ip: item.ip,
+            reason: item.reason,
+            timestamp: item.timestamp,
+        })
+        .collect::<Vec<_>>();
+    metric(&server_config.server_db_dir, &auth.username);
+    Ok(AuthFailuresResponse { failures })
+}
+
+fn metric(db_path: &String, user: &String) {
+    let metrics = Metrics::new(db_path);
+    let _ = metrics.note_route(&"authFailures".to_string(), user);
+}
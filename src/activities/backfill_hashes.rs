@@ -0,0 +1,100 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use rocket::{post, tokio, State};
+
+use crate::{
+    authenticated::Admin, config::server_config::ServerConfig, data::metrics::Metrics,
+    data_validation::hash_file, model::MusicUploaderError,
+};
+
+/// hashes every song in upload_dir that doesn't have a hash yet, so duplicates of songs that
+/// were added before hashes were tracked (or outside of music uploader) are caught too.
+#[post("/backfillhashes")]
+pub async fn backfill_hashes(
+    auth: Admin,
+    server_config: &State<ServerConfig>,
+) -> Result<String, MusicUploaderError> {
+    println!("{} is backfilling song hashes", auth.username);
+    let upload_dir = PathBuf::from(&server_config.upload_dir);
+    let valid_extensions = server_config.valid_extensions.clone();
+    let db_path = server_config.server_db_dir.clone();
+    // hashing the whole library takes a while, keep it off the async workers.
+    let (hashed, failed) = tokio::task::spawn_blocking(move || {
+        backfill(&upload_dir, &valid_extensions, &Metrics::new(&db_path))
+    })
+    .await
+    .map_err(|e| MusicUploaderError::InternalServerError(e.to_string()))??;
+    println!("backfilled {hashed} hashes, {failed} songs failed");
+    metric(&server_config.server_db_dir, &auth.username);
+    Ok(format!("hashed {hashed} songs, {failed} failed"))
+}
+
+fn backfill(
+    upload_dir: &Path,
+    valid_extensions: &[String],
+    metrics: &Metrics,
+) -> Result<(u32, u32), MusicUploaderError> {
+    let hashed_paths =
+        metrics
+            .get_hashed_paths()
+            .ok_or(MusicUploaderError::InternalServerError(
+                "failed to get the already hashed songs".to_string(),
+            ))?;
+    let (mut hashed, mut failed) = (0, 0);
+    for song_path in find_songs(upload_dir, valid_extensions) {
+        let song_path_str = song_path.to_string_lossy().to_string();
+        if hashed_paths.contains(&song_path_str) {
+            continue;
+        }
+        let noted = hash_file(&song_path)
+            .inspect_err(|e| println!("failed to hash {song_path_str}: {e}"))
+            .is_ok_and(|hash| metrics.note_hash(&song_path_str, &hash));
+        match noted {
+            true => hashed += 1,
+            false => failed += 1,
+        }
+    }
+    Ok((hashed, failed))
+}
+
+/// every file below dir with a valid extension, skipping hidden files like partial writes.
+fn find_songs(dir: &Path, valid_extensions: &[String]) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("failed to read directory {dir:?}: {e}");
+            return Vec::new();
+        }
+    };
+    let mut songs = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => {
+                songs.extend(find_songs(&path, valid_extensions))
+            }
+            Ok(file_type) if file_type.is_file() => {
+                let extension = path
+                    .extension()
+                    .map(|extension| extension.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                if valid_extensions.contains(&extension) {
+                    songs.push(path);
+                }
+            }
+            _ => {}
+        }
+    }
+    songs
+}
+
+fn metric(db_path: &String, user: &String) {
+    let metrics = Metrics::new(db_path);
+    let _ = metrics.note_route(&"backfillHashes".to_string(), user);
+}
@@ -0,0 +1,100 @@
+use rocket::{
+    get, http, post,
+    request::{self, FromRequest},
+    Request, State,
+};
+
+use crate::{
+    authenticated::Uploader,
+    batch::{create_batch, get_batch_status},
+    config::server_config::ServerConfig,
+    data::metrics::Metrics,
+    model::{BatchResponse, HeaderError, MusicUploaderError},
+    rocket_utils::get_header_value,
+};
+
+pub struct DeclareBatchHeaders {
+    artist: String,
+    album: String,
+    files: String,
+}
+
+pub struct BatchStatusHeaders {
+    batch: String,
+}
+
+/// declares the files of an album upload. uploads sent with the returned id in a `batch` header
+/// count towards it, and plex scans once they are all in or the batch times out.
+#[post("/declarebatch")]
+pub async fn declare_batch(
+    auth: Uploader,
+    server_config: &State<ServerConfig>,
+    headers: DeclareBatchHeaders,
+) -> Result<BatchResponse, MusicUploaderError> {
+    let id = create_batch(
+        server_config,
+        &auth.username,
+        &headers.artist,
+        &headers.album,
+        &headers.files,
+    )?;
+    metric(&server_config.server_db_dir, "declareBatch", &auth.username);
+    get_batch_status(server_config, &id, &auth)
+}
+
+#[get("/batchstatus")]
+pub async fn batch_status(
+    auth: Uploader,
+    server_config: &State<ServerConfig>,
+    headers: BatchStatusHeaders,
+) -> Result<BatchResponse, MusicUploaderError> {
+    metric(&server_config.server_db_dir, "batchStatus", &auth.username);
+    get_batch_status(server_config, &headers.batch, &auth)
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for DeclareBatchHeaders {
+    type Error = HeaderError;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match Self::from_request_inner(req).await {
+            Ok(a) => request::Outcome::Success(a),
+            Err(e) => request::Outcome::Error((http::Status::BadRequest, e)),
+        }
+    }
+}
+
+impl<'r> DeclareBatchHeaders {
+    async fn from_request_inner(req: &'r Request<'_>) -> Result<Self, HeaderError> {
+        let headers = req.headers();
+        Ok(Self {
+            artist: get_header_value(headers, "artist")?,
+            album: get_header_value(headers, "album")?,
+            files: get_header_value(headers, "files")?,
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for BatchStatusHeaders {
+    type Error = HeaderError;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match Self::from_request_inner(req).await {
+            Ok(a) => request::Outcome::Success(a),
+            Err(e) => request::Outcome::Error((http::Status::BadRequest, e)),
+        }
+    }
+}
+
+impl<'r> BatchStatusHeaders {
+    async fn from_request_inner(req: &'r Request<'_>) -> Result<Self, HeaderError> {
+        let headers = req.headers();
+        Ok(Self {
+            batch: get_header_value(headers, "batch")?,
+        })
+    }
+}
+
+fn metric(db_path: &String, route: &str, user: &String) {
+    let metrics = Metrics::new(db_path);
+    let _ = metrics.note_route(&route.to_string(), user);
+}
@@ -0,0 +1,127 @@
+use rocket::data::{Data, ToByteUnit};
+use rocket::request::FromRequest;
+use rocket::{http, post, request, Request, State};
+
+use crate::authenticated::Uploader;
+use crate::config::server_config::ServerConfig;
+use crate::content_sniffing::{get_image_info, ImageInfo};
+use crate::cover_art::find_cover_art;
+use crate::data::metrics::Metrics;
+use crate::data_validation::{check_hash, stream_to_temp_file};
+use crate::model::{HeaderError, MusicUploaderError};
+use crate::path_utils::{find_album_dir, get_library_path};
+use crate::rocket_utils::{get_header_value, get_optional_header_value};
+
+#[derive(Debug)]
+pub struct CoverArtHeaders {
+    hash: String,
+    album: String,
+    artist: String,
+    replace: bool,
+}
+
+#[post("/coverart", data = "<data>")]
+pub async fn upload_cover_art(
+    auth: Uploader,
+    server_config: &State<ServerConfig>,
+    headers: CoverArtHeaders,
+    data: Data<'_>,
+) -> Result<String, MusicUploaderError> {
+    println!(
+        "\n{} is trying to upload cover art {:?}",
+        auth.username, headers
+    );
+    let result = upload_cover_art_inner(server_config, &headers, data).await;
+    match &result {
+        Ok(cover_path) => println!("cover art is now at {cover_path}"),
+        Err(e) => println!("error: {e}"),
+    }
+    metric(&server_config.server_db_dir, &auth.username);
+    result.map(|cover_path| format!("uploaded cover art: {cover_path}"))
+}
+
+async fn upload_cover_art_inner(
+    server_config: &ServerConfig,
+    headers: &CoverArtHeaders,
+    data: Data<'_>,
+) -> Result<String, MusicUploaderError> {
+    // covers only go with songs we already have, so we never create an empty album for plex.
+    let metrics = Metrics::new(&server_config.server_db_dir);
+    let album_dir = find_album_dir(server_config, &metrics, &headers.artist, &headers.album)
+        .ok_or(MusicUploaderError::ConstraintViolation(format!(
+            "there is no album directory for {} by {}, upload the album's songs first",
+            headers.album, headers.artist
+        )))?;
+    let existing_covers = find_cover_art(&album_dir);
+    if !existing_covers.is_empty() && !headers.replace {
+        return Err(MusicUploaderError::ConstraintViolation(format!(
+            "{} already has cover art, send replace to overwrite it",
+            get_library_path(server_config, &album_dir)
+        )));
+    }
+    let max_bytes = server_config.cover_art_max_mb.megabytes();
+    let upload = stream_to_temp_file(data, max_bytes, &server_config.temp_file_dir).await?;
+    check_hash(&headers.hash, &upload.hash)?;
+    let image = get_image_info(&upload.read_start(upload.size as usize)?).ok_or(
+        MusicUploaderError::ConstraintViolation("cover art must be a jpeg or png".to_string()),
+    )?;
+    validate_dimensions(server_config, &image)?;
+    let cover_path = album_dir.join(format!("cover.{}", image.format.extension()));
+    match headers.replace {
+        true => upload.persist_replacing(&cover_path)?,
+        false => upload.persist(&cover_path)?,
+    }
+    // a leftover cover.png would compete with a new cover.jpg.
+    for old_cover in existing_covers.iter().filter(|old| **old != cover_path) {
+        let _ = std::fs::remove_file(old_cover)
+            .inspect_err(|e| println!("failed to remove old cover art {old_cover:?}: {e}"));
+    }
+    Ok(get_library_path(server_config, &cover_path))
+}
+
+fn validate_dimensions(
+    server_config: &ServerConfig,
+    image: &ImageInfo,
+) -> Result<(), MusicUploaderError> {
+    let max_dimension = server_config.cover_art_max_dimension;
+    if image.width == 0 || image.height == 0 {
+        return Err(MusicUploaderError::ConstraintViolation(
+            "cover art has no pixels".to_string(),
+        ));
+    }
+    if image.width > max_dimension || image.height > max_dimension {
+        return Err(MusicUploaderError::ConstraintViolation(format!(
+            "cover art is {}x{}, it can be at most {max_dimension}x{max_dimension}",
+            image.width, image.height
+        )));
+    }
+    Ok(())
+}
+
+fn metric(db_path: &String, user: &String) {
+    let metrics = Metrics::new(db_path);
+    let _ = metrics.note_route(&"coverArt".to_string(), user);
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CoverArtHeaders {
+    type Error = HeaderError;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match Self::from_request_inner(req).await {
+            Ok(a) => request::Outcome::Success(a),
+            Err(e) => request::Outcome::Error((http::Status::BadRequest, e)),
+        }
+    }
+}
+
+impl<'r> CoverArtHeaders {
+    async fn from_request_inner(req: &'r Request<'_>) -> Result<Self, HeaderError> {
+        let headers = req.headers();
+        Ok(Self {
+            hash: get_header_value(headers, "hash")?,
+            album: get_header_value(headers, "album")?,
+            artist: get_header_value(headers, "artist")?,
+            replace: get_optional_header_value(headers, "replace")?.unwrap_or(false),
+        })
+    }
+}
@@ -0,0 +1,205 @@
+use rocket::{
+    http, post,
+    request::{self, FromRequest},
+    Request, State,
+};
+use rocket_basicauth::BasicAuth;
+
+use crate::{
+    authenticated::{Admin, Authenticator},
+    config::{secrets_config::Role, server_config::ServerConfig},
+    data::{metrics::Metrics, operational_data::OperationalData},
+    model::{HeaderError, InviteResponse, MusicUploaderError},
+    password_utils::{generate_secret_token, hash_password, hash_secret_token},
+    rocket_utils::{get_header_value, get_optional_header_value},
+    time_utils::get_now_timestamp,
+};
+
+const ONE_HOUR_IN_SECONDS: i64 = 60 * 60;
+const DEFAULT_INVITE_LIFETIME_HOURS: u32 = 72;
+const MIN_USERNAME_LENGTH: usize = 3;
+const MAX_USERNAME_LENGTH: usize = 32;
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+pub struct CreateInviteHeaders {
+    role: Role,
+    lifetime_hours: u32,
+}
+
+pub struct RegisterHeaders {
+    invite: String,
+}
+
+pub struct RemoveUserHeaders {
+    user: String,
+}
+
+#[post("/invite")]
+pub async fn create_invite(
+    auth: Admin,
+    server_config: &State<ServerConfig>,
+    headers: CreateInviteHeaders,
+) -> Result<InviteResponse, MusicUploaderError> {
+    println!("{} is creating a {:?} invite", auth.username, headers.role);
+    let code = generate_secret_token();
+    let expires = get_now_timestamp() + headers.lifetime_hours as i64 * ONE_HOUR_IN_SECONDS;
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    operational_data
+        .create_invite(
+            &hash_secret_token(&code),
+            headers.role,
+            &auth.username,
+            expires,
+        )
+        .ok_or(MusicUploaderError::InternalServerError(
+            "Failed to create invite".to_string(),
+        ))?;
+    metric(&server_config.server_db_dir, "createInvite", &auth.username);
+    Ok(InviteResponse {
+        code,
+        role: headers.role.as_str().to_string(),
+        expires,
+    })
+}
+
+/// redeems an invite code, the basic auth credentials become the new user's username and password.
+#[post("/register")]
+pub async fn register(
+    credentials: BasicAuth,
+    authenticator: &State<Authenticator>,
+    server_config: &State<ServerConfig>,
+    headers: RegisterHeaders,
+) -> Result<String, MusicUploaderError> {
+    let username = credentials.username.trim().to_string();
+    println!("{username} is trying to register");
+    validate_credentials(&username, &credentials.password)?;
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    if authenticator.is_username_taken(&operational_data, &username) {
+        return Err(MusicUploaderError::ConstraintViolation(
+            "username is already taken".to_string(),
+        ));
+    }
+    let password_hash = hash_password(&credentials.password)
+        .map_err(|e| MusicUploaderError::InternalServerError(e.to_string()))?;
+    let role = operational_data
+        .redeem_invite(
+            &hash_secret_token(&headers.invite),
+            &username,
+            &password_hash,
+        )
+        .ok_or(MusicUploaderError::ConstraintViolation(
+            "invite is invalid, expired or already used".to_string(),
+        ))?;
+    println!("{username} registered as {role:?}");
+    metric(&server_config.server_db_dir, "register", &username);
+    Ok(format!("registered {username} as {}", role.as_str()))
+}
+
+/// deletes a registered user and revokes their api tokens, so neither their password nor a token works.
+#[post("/removeuser")]
+pub async fn remove_user(
+    auth: Admin,
+    authenticator: &State<Authenticator>,
+    server_config: &State<ServerConfig>,
+    headers: RemoveUserHeaders,
+) -> Result<String, MusicUploaderError> {
+    println!("{} is removing {}", auth.username, headers.user);
+    if authenticator.is_secrets_user(&headers.user) {
+        return Err(MusicUploaderError::ConstraintViolation(format!(
+            "{} is in Secrets.toml, remove them from there",
+            headers.user
+        )));
+    }
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let removed = operational_data.remove_managed_user(&headers.user).ok_or(
+        MusicUploaderError::InternalServerError("Failed to remove user".to_string()),
+    )?;
+    if !removed {
+        return Err(MusicUploaderError::ConstraintViolation(format!(
+            "there is no registered user {}",
+            headers.user
+        )));
+    }
+    let revoked = operational_data
+        .revoke_user_api_tokens(&headers.user)
+        .ok_or(MusicUploaderError::InternalServerError(
+            "Failed to revoke api tokens".to_string(),
+        ))?;
+    metric(&server_config.server_db_dir, "removeUser", &auth.username);
+    Ok(format!(
+        "removed {} and revoked {revoked} api tokens",
+        headers.user
+    ))
+}
+
+fn validate_credentials(username: &str, password: &str) -> Result<(), MusicUploaderError> {
+    let username_length = username.chars().count();
+    if !(MIN_USERNAME_LENGTH..=MAX_USERNAME_LENGTH).contains(&username_length) {
+        return Err(MusicUploaderError::ConstraintViolation(format!(
+            "username must be between {MIN_USERNAME_LENGTH} and {MAX_USERNAME_LENGTH} characters"
+        )));
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+    {
+        return Err(MusicUploaderError::ConstraintViolation(
+            "username may only contain letters, numbers, '_', '-' and '.'".to_string(),
+        ));
+    }
+    if password.trim().chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(MusicUploaderError::ConstraintViolation(format!(
+            "password must be at least {MIN_PASSWORD_LENGTH} characters"
+        )));
+    }
+    Ok(())
+}
+
+fn metric(db_path: &String, route: &str, user: &String) {
+    let metrics = Metrics::new(db_path);
+    let _ = metrics.note_route(&route.to_string(), user);
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CreateInviteHeaders {
+    type Error = HeaderError;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match Self::from_request_inner(req).await {
+            Ok(a) => request::Outcome::Success(a),
+            Err(e) => request::Outcome::Error((http::Status::BadRequest, e)),
+        }
+    }
+}
+
+impl<'r> CreateInviteHeaders {
+    async fn from_request_inner(req: &'r Request<'_>) -> Result<Self, HeaderError> {
+        let headers = req.headers();
+        Ok(Self {
+            role: get_optional_header_value(headers, "role")?.unwrap_or_default(),
+            lifetime_hours: get_optional_header_value(headers, "lifetimehours")?
+                .unwrap_or(DEFAULT_INVITE_LIFETIME_HOURS),
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RemoveUserHeaders {
+    type Error = HeaderError;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match get_header_value(req.headers(), "user") {
+            Ok(user) => request::Outcome::Success(Self { user }),
+            Err(e) => request::Outcome::Error((http::Status::BadRequest, e)),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RegisterHeaders {
+    type Error = HeaderError;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match get_header_value(req.headers(), "invite") {
+            Ok(invite) => request::Outcome::Success(Self { invite }),
+            Err(e) => request::Outcome::Error((http::Status::BadRequest, e)),
+        }
+    }
+}
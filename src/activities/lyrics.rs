@@ -0,0 +1,105 @@
+use rocket::data::{Data, ToByteUnit};
+use rocket::request::FromRequest;
+use rocket::{http, post, request, Request, State};
+
+use crate::activities::upload::find_song_by_hash;
+use crate::authenticated::Uploader;
+use crate::config::server_config::ServerConfig;
+use crate::data::metrics::Metrics;
+use crate::data_validation::{check_hash, stream_to_temp_file};
+use crate::lyrics::validate_lrc;
+use crate::model::{HeaderError, MusicUploaderError};
+use crate::path_utils::{build_sidecar_path, get_library_path};
+use crate::rocket_utils::{get_header_value, get_optional_header_value};
+
+/// lyrics are text, anything bigger than this is not an lrc file.
+const MAX_LYRICS_KB: u32 = 512;
+
+#[derive(Debug)]
+pub struct LyricsHeaders {
+    hash: String,
+    song_hash: String,
+    replace: bool,
+}
+
+#[post("/lyrics", data = "<data>")]
+pub async fn upload_lyrics(
+    auth: Uploader,
+    server_config: &State<ServerConfig>,
+    headers: LyricsHeaders,
+    data: Data<'_>,
+) -> Result<String, MusicUploaderError> {
+    println!(
+        "\n{} is trying to upload lyrics {:?}",
+        auth.username, headers
+    );
+    let result = upload_lyrics_inner(server_config, &headers, data).await;
+    match &result {
+        Ok(lyrics_path) => println!("lyrics are now at {lyrics_path}"),
+        Err(e) => println!("error: {e}"),
+    }
+    metric(&server_config.server_db_dir, &auth.username);
+    result.map(|lyrics_path| format!("uploaded lyrics: {lyrics_path}"))
+}
+
+async fn upload_lyrics_inner(
+    server_config: &ServerConfig,
+    headers: &LyricsHeaders,
+    data: Data<'_>,
+) -> Result<String, MusicUploaderError> {
+    // lyrics only make sense next to a song we already have.
+    let metrics = Metrics::new(&server_config.server_db_dir);
+    let song_path = find_song_by_hash(&metrics, &headers.song_hash)?.ok_or(
+        MusicUploaderError::ConstraintViolation(
+            "there is no song in the library with that songhash".to_string(),
+        ),
+    )?;
+    let lyrics_path = build_sidecar_path(&song_path, "lrc", server_config.sanitize_mode)
+        .map_err(|e| MusicUploaderError::ValidateDirectoryError(Box::new(e)))?;
+    if lyrics_path.exists() && !headers.replace {
+        return Err(MusicUploaderError::ConstraintViolation(format!(
+            "{} already has lyrics, send replace to overwrite them",
+            get_library_path(server_config, &song_path)
+        )));
+    }
+    let upload = stream_to_temp_file(
+        data,
+        MAX_LYRICS_KB.kibibytes(),
+        &server_config.temp_file_dir,
+    )
+    .await?;
+    check_hash(&headers.hash, &upload.hash)?;
+    validate_lrc(&upload.read_start(upload.size as usize)?)?;
+    match headers.replace {
+        true => upload.persist_replacing(&lyrics_path)?,
+        false => upload.persist(&lyrics_path)?,
+    }
+    Ok(get_library_path(server_config, &lyrics_path))
+}
+
+fn metric(db_path: &String, user: &String) {
+    let metrics = Metrics::new(db_path);
+    let _ = metrics.note_route(&"lyrics".to_string(), user);
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LyricsHeaders {
+    type Error = HeaderError;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match Self::from_request_inner(req).await {
+            Ok(a) => request::Outcome::Success(a),
+            Err(e) => request::Outcome::Error((http::Status::BadRequest, e)),
+        }
+    }
+}
+
+impl<'r> LyricsHeaders {
+    async fn from_request_inner(req: &'r Request<'_>) -> Result<Self, HeaderError> {
+        let headers = req.headers();
+        Ok(Self {
+            hash: get_header_value(headers, "hash")?,
+            song_hash: get_header_value(headers, "songhash")?,
+            replace: get_optional_header_value(headers, "replace")?.unwrap_or(false),
+        })
+    }
+}
@@ -1,6 +1,17 @@
+pub mod alias;
+pub mod api_token;
+pub mod auth_failures;
+pub mod backfill_hashes;
+pub mod batch;
+pub mod cover_art;
+pub mod invite;
+pub mod lyrics;
 pub mod multipart_upload;
+pub mod quarantine;
 pub mod search;
 pub mod simple_routes;
 pub mod trigger_scan;
 pub mod upload;
-pub mod public_playlists;
\ No newline at end of file
+pub mod public_playlists;
+pub mod quota;
+pub mod reload_users;
\ No newline at end of file
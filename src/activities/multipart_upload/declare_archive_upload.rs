@@ -0,0 +1,100 @@
+use rocket::{data::ToByteUnit, post, State};
+
+use crate::{
+    activities::multipart_upload::{
+        declare_upload::{
+            get_received_parts, prepare_upload_state, validate_inputs, DeclareUploadHeaders,
+        },
+        finalize_archive_upload::finalize_archive_upload,
+    },
+    authenticated::{Authenticator, Uploader},
+    config::server_config::ServerConfig,
+    data::{metrics::Metrics, operational_data::OperationalData},
+    model::{DeclareArchiveResponse, MusicUploaderError},
+    path_utils::build_album_path,
+    quota::Quota,
+};
+
+/// declares a zip of an album, the parts are sent with /uploadpart like any other multi part upload.
+/// declaring again once every part is in extracts the archive and reports on each song.
+#[post("/declarearchive")]
+pub async fn declare_archive_upload(
+    auth: Uploader,
+    authenticator: &State<Authenticator>,
+    server_config: &State<ServerConfig>,
+    headers: DeclareUploadHeaders,
+) -> Result<DeclareArchiveResponse, MusicUploaderError> {
+    let quota = authenticator.get_quota(
+        server_config,
+        &OperationalData::new(&server_config.server_operational_db_dir),
+        &auth.username,
+    );
+    declare_archive_upload_inner(&auth.username, server_config, headers, quota).await
+}
+
+async fn declare_archive_upload_inner(
+    username: &String,
+    server_config: &State<ServerConfig>,
+    headers: DeclareUploadHeaders,
+    quota: Quota,
+) -> Result<DeclareArchiveResponse, MusicUploaderError> {
+    if !headers.file_name.to_lowercase().ends_with(".zip") {
+        return Err(MusicUploaderError::ConstraintViolation(
+            "archives must be .zip files".to_string(),
+        ));
+    }
+    if headers.replace {
+        return Err(MusicUploaderError::ConstraintViolation(
+            "archives can't replace songs, upload them one at a time instead".to_string(),
+        ));
+    }
+    validate_inputs(&headers, server_config)?;
+    if headers.declared_size_bytes as u64 > server_config.max_archive_mb.megabytes().as_u64() {
+        return Err(MusicUploaderError::ConstraintViolation(
+            "archive is too large".to_string(),
+        ));
+    }
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    // the songs are checked one by one once extracted, this only stops an archive that can't fit.
+    quota.check_user(
+        &Metrics::new(&server_config.server_db_dir),
+        &operational_data,
+        username,
+        headers.declared_size_bytes as u64,
+    )?;
+    let album_dir = build_album_path(server_config, &headers.artist, &headers.album);
+    let album_dir_str = album_dir.to_string_lossy().to_string();
+    println!("new archive upload from {username} for album directory: {album_dir_str}");
+    let upload_declaration = prepare_upload_state(
+        &headers,
+        &operational_data,
+        &album_dir_str,
+        username,
+        server_config,
+    )?;
+    let expected_num_parts = upload_declaration.get_expected_num_parts();
+    let received_parts =
+        get_received_parts(&operational_data, &upload_declaration.key).map_err(|e| {
+            MusicUploaderError::InternalServerError(format!(
+                "Failed to parse received parts: {e:?}"
+            ))
+        })?;
+    metric(&server_config.server_db_dir, username);
+    if received_parts.len() as u32 >= expected_num_parts {
+        let entries =
+            finalize_archive_upload(upload_declaration, server_config, operational_data, &quota)
+                .await?;
+        return Ok(DeclareArchiveResponse::Complete { entries });
+    }
+    Ok(DeclareArchiveResponse::Incomplete {
+        key: upload_declaration.key,
+        declared_size: upload_declaration.declared_size,
+        part_size: upload_declaration.part_size,
+        received_parts,
+    })
+}
+
+fn metric(db_path: &String, user: &String) {
+    let metrics = Metrics::new(db_path);
+    let _ = metrics.note_route(&"declarearchive".to_string(), user);
+}
@@ -1,3 +1,5 @@
+use std::path::Path;
+
 use rocket::{
     data::ToByteUnit,
     http, post,
@@ -6,65 +8,95 @@ use rocket::{
 };
 
 use crate::{
-    activities::multipart_upload::finalize_part_upload::{cleanup_upload, finalize_part_upload},
-    authenticated::Authenticated,
+    activities::{
+        multipart_upload::finalize_part_upload::{cleanup_upload, finalize_part_upload},
+        upload::{check_can_upload_to, check_not_duplicate, map_path_error},
+    },
+    authenticated::{Authenticator, Uploader},
+    batch::{check_batch_file, finish_batch_file, skip_batch_file},
     config::server_config::ServerConfig,
     data::{
         metrics::Metrics,
-        operational_data::{OperationalData, UploadDeclarationItem},
+        operational_data::{DeclaredSong, OperationalData, UploadDeclarationItem},
     },
     model::{DeclareUploadResponse, HeaderError, MusicUploaderError},
-    path_utils::{build_and_validate_path, ValidateDirectoryError},
-    rocket_utils::get_header_value,
+    path_template::SongFields,
+    path_utils::build_path,
+    quota::Quota,
+    rocket_utils::{get_header_value, get_optional_header_value},
 };
 
 #[derive(Debug)]
 pub struct DeclareUploadHeaders {
-    hash: String,
-    file_name: String,
-    album: String,
-    artist: String,
-    declared_size_bytes: u32,
-    part_size_bytes: u32,
+    pub hash: String,
+    pub file_name: String,
+    pub album: String,
+    pub artist: String,
+    pub declared_size_bytes: u32,
+    pub part_size_bytes: u32,
+    pub replace: bool,
+    pub batch: Option<String>,
 }
 
 #[post("/declareupload")]
 pub async fn declare_upload(
-    auth: Authenticated,
+    auth: Uploader,
+    authenticator: &State<Authenticator>,
     server_config: &State<ServerConfig>,
     headers: DeclareUploadHeaders,
 ) -> Result<DeclareUploadResponse, MusicUploaderError> {
-    declare_upload_inner(auth, server_config, headers).await
+    let quota = authenticator.get_quota(
+        server_config,
+        &OperationalData::new(&server_config.server_operational_db_dir),
+        &auth.username,
+    );
+    let batch = headers.batch.clone();
+    let file_name = headers.file_name.clone();
+    let result = declare_upload_inner(auth, server_config, headers, quota).await;
+    if let (Some(batch), Err(e)) = (&batch, &result) {
+        skip_batch_file(server_config, batch, &file_name, e);
+    }
+    result
 }
 
 async fn declare_upload_inner(
-    auth: Authenticated,
+    auth: Uploader,
     server_config: &State<ServerConfig>,
     headers: DeclareUploadHeaders,
+    quota: Quota,
 ) -> Result<DeclareUploadResponse, MusicUploaderError> {
-    let dir = build_and_validate_path(
-        server_config,
-        &headers.artist,
-        &headers.album,
-        &headers.file_name,
-    )
-    .await
-    .map_err(|e| match e {
-        ValidateDirectoryError::FileAlreadyExists => MusicUploaderError::SongAlreadyExists,
-        e => MusicUploaderError::ValidateDirectoryError(Box::new(e)),
-    })?;
+    if let Some(batch) = &headers.batch {
+        check_batch_file(server_config, batch, &auth.username, &headers.file_name)?;
+    }
+    let header_song = SongFields::new(&headers.artist, &headers.album, &headers.file_name, None);
+    let dir = build_path(server_config, &header_song).map_err(map_path_error)?;
+    let metrics = Metrics::new(&server_config.server_db_dir);
+    let replace_as = headers.replace.then_some(&*auth);
+    check_can_upload_to(server_config, &metrics, &dir, replace_as)?;
     validate_inputs(&headers, server_config)?;
+    check_not_duplicate(server_config, &metrics, &headers.hash)?;
     let dir_str = dir
         .to_str()
         .ok_or(MusicUploaderError::InternalServerError(format!(
             "Failed to convert dir to dir_str: {dir:?}"
         )))?
         .to_string();
-    let username = auth.username;
+    let username = &auth.username;
     println!("new multi part upload from {username} using directory: {dir_str}");
     let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
-    let upload_declaration =
-        prepare_upload_state(&headers, &operational_data, &dir_str, server_config)?;
+    quota.check_user(
+        &metrics,
+        &operational_data,
+        username,
+        headers.declared_size_bytes as u64,
+    )?;
+    let upload_declaration = prepare_upload_state(
+        &headers,
+        &operational_data,
+        &dir_str,
+        username,
+        server_config,
+    )?;
     let expected_num_parts = upload_declaration.get_expected_num_parts();
     let received_parts =
         get_received_parts(&operational_data, &upload_declaration.key).map_err(|e| {
@@ -73,10 +105,21 @@ async fn declare_upload_inner(
             ))
         })?;
     if received_parts.len() as u32 >= expected_num_parts {
-        finalize_part_upload(upload_declaration, server_config, operational_data).await?;
+        let song_path = finalize_part_upload(
+            upload_declaration,
+            server_config,
+            operational_data,
+            &quota,
+            replace_as,
+        )
+        .await?;
+        if let Some(batch) = &headers.batch {
+            let song_dir = song_path.as_deref().and_then(Path::parent);
+            finish_batch_file(server_config, batch, &headers.file_name, song_dir);
+        }
         return Ok(DeclareUploadResponse::Complete);
     }
-    metric(&server_config.server_db_dir, &username);
+    metric(&server_config.server_db_dir, username);
     Ok(DeclareUploadResponse::Incomplete {
         key: upload_declaration.key,
         declared_size: upload_declaration.declared_size,
@@ -85,7 +128,7 @@ async fn declare_upload_inner(
     })
 }
 
-fn validate_inputs(
+pub fn validate_inputs(
     headers: &DeclareUploadHeaders,
     server_config: &State<ServerConfig>,
 ) -> Result<(), MusicUploaderError> {
@@ -105,10 +148,11 @@ fn validate_inputs(
 
 /// If there is an ongoing upload, then we get the state.  However, if the incoming state varies in ways that matter
 /// (like the part_size is smaller) then we should delete the current upload state and start anew.
-fn prepare_upload_state(
+pub fn prepare_upload_state(
     incoming_upload_state: &DeclareUploadHeaders,
     operational_data: &OperationalData,
     dir_str: &String,
+    username: &str,
     server_config: &State<ServerConfig>,
 ) -> Result<UploadDeclarationItem, MusicUploaderError> {
     let try_twice: usize = 2;
@@ -119,6 +163,12 @@ fn prepare_upload_state(
                 incoming_upload_state.declared_size_bytes,
                 incoming_upload_state.part_size_bytes,
                 dir_str.to_string(),
+                username.to_string(),
+                DeclaredSong {
+                    artist: incoming_upload_state.artist.clone(),
+                    album: incoming_upload_state.album.clone(),
+                    file_name: incoming_upload_state.file_name.clone(),
+                },
             )
             .ok_or(MusicUploaderError::InternalServerError(
                 "Failed to declare upload in db".to_string(),
@@ -145,12 +195,12 @@ fn is_upload_declaration_good_to_use(
 }
 
 #[derive(Debug)]
-enum GetReceivedPartsError {
+pub enum GetReceivedPartsError {
     TooLargeOfIndices,
     QueryError,
 }
 
-fn get_received_parts(
+pub fn get_received_parts(
     operational_data: &OperationalData,
     key: &str,
 ) -> Result<Vec<u8>, GetReceivedPartsError> {
@@ -188,6 +238,8 @@ impl<'r> DeclareUploadHeaders {
             artist: get_header_value(headers, "artist")?,
             declared_size_bytes: get_header_value(headers, "declaredsize")?,
             part_size_bytes: get_header_value(headers, "partsize")?,
+            replace: get_optional_header_value(headers, "replace")?.unwrap_or(false),
+            batch: get_optional_header_value(headers, "batch")?,
         })
     }
 }
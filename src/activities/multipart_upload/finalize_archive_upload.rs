@@ -0,0 +1,306 @@
+use std::{
+    collections::HashSet,
+    ffi::OsStr,
+    fs::File,
+    path::{Component, Path, PathBuf},
+};
+
+use rocket::{data::ToByteUnit, State};
+use zip::ZipArchive;
+
+use crate::{
+    activities::{
+        multipart_upload::finalize_part_upload::{cleanup_upload, get_parts},
+        upload::{check_not_duplicate, map_path_error, resolve_song_path},
+    },
+    config::server_config::ServerConfig,
+    content_sniffing::validate_file_content,
+    cover_art::extract_cover_art,
+    data::{
+        metrics::Metrics,
+        operational_data::{OperationalData, UploadDeclarationItem},
+    },
+    data_validation::{check_hash, concatenate_to_temp_file, copy_to_temp_file, StreamedUpload},
+    model::{ArchiveEntryReport, ArchiveEntryStatus, MusicUploaderError},
+    path_template::SongFields,
+    path_utils::{build_path, get_library_path},
+    quarantine::{is_moderated, quarantine_song},
+    quota::Quota,
+    replace::persist_song,
+};
+
+/// more files than any album should have, so a hostile archive can't keep the server busy forever.
+const MAX_ARCHIVE_ENTRIES: usize = 1000;
+/// folders macOS adds to zips with resource forks in them.
+const MACOS_METADATA_DIR: &str = "__MACOSX";
+
+/// extracts every song in the archive into the declared album, each one going through the same
+/// checks as a single upload. the parts are cleaned up either way, since sending the same archive
+/// again would not change the outcome.
+pub async fn finalize_archive_upload(
+    upload_declaration: UploadDeclarationItem,
+    server_config: &State<ServerConfig>,
+    operational_data: OperationalData,
+    quota: &Quota,
+) -> Result<Vec<ArchiveEntryReport>, MusicUploaderError> {
+    let result = finalize_archive_upload_inner(&upload_declaration, server_config, quota).await;
+    cleanup_upload(&upload_declaration.key, &operational_data, server_config)?;
+    result
+}
+
+async fn finalize_archive_upload_inner(
+    upload_declaration: &UploadDeclarationItem,
+    server_config: &ServerConfig,
+    quota: &Quota,
+) -> Result<Vec<ArchiveEntryReport>, MusicUploaderError> {
+    let archive = join_parts(upload_declaration, server_config)?;
+    let file = archive.open().map_err(|e| {
+        MusicUploaderError::InternalServerError(format!("Failed to open archive: {e}"))
+    })?;
+    let mut zip = ZipArchive::new(file).map_err(|e| {
+        MusicUploaderError::ConstraintViolation(format!("not a valid zip archive: {e}"))
+    })?;
+    if zip.len() > MAX_ARCHIVE_ENTRIES {
+        return Err(MusicUploaderError::ConstraintViolation(format!(
+            "archive has more than {MAX_ARCHIVE_ENTRIES} files"
+        )));
+    }
+    let shared_file_names = find_shared_file_names(zip.file_names());
+    let mut used_file_names = HashSet::new();
+    let mut reports = Vec::new();
+    for index in 0..zip.len() {
+        let (name, result) = match extract_entry(&mut zip, index, server_config, &shared_file_names)
+        {
+            Some((name, Ok((file_name, _)))) if !used_file_names.insert(file_name.clone()) => (
+                name,
+                Err(MusicUploaderError::ConstraintViolation(format!(
+                    "another file in the archive is also going to {file_name}"
+                ))),
+            ),
+            Some((name, Ok((file_name, upload)))) => {
+                let result =
+                    add_archive_entry(server_config, quota, upload_declaration, &file_name, upload)
+                        .await;
+                (name, result)
+            }
+            Some((name, Err(e))) => (name, Err(e)),
+            None => continue,
+        };
+        println!("archive entry {name}: {result:?}");
+        reports.push(build_report(server_config, name, result));
+    }
+    Ok(reports)
+}
+
+fn join_parts(
+    upload_declaration: &UploadDeclarationItem,
+    server_config: &ServerConfig,
+) -> Result<StreamedUpload, MusicUploaderError> {
+    let mut parts = get_parts(
+        &upload_declaration.key,
+        &OperationalData::new(&server_config.server_operational_db_dir),
+    )?;
+    parts.sort();
+    let base_path = Path::new(&server_config.temp_file_dir);
+    let part_paths = parts
+        .iter()
+        .map(|part| base_path.join(part.part_file_name()))
+        .collect::<Vec<_>>();
+    let archive = concatenate_to_temp_file(&part_paths, &server_config.temp_file_dir)?;
+    if upload_declaration.declared_size as u64 != archive.size {
+        return Err(MusicUploaderError::ConstraintViolation(
+            "total file is not the expected size".to_string(),
+        ));
+    }
+    check_hash(&upload_declaration.hash, &archive.hash)?;
+    Ok(archive)
+}
+
+/// the entry's file name and contents.
+type ExtractedEntry = Result<(String, StreamedUpload), MusicUploaderError>;
+
+/// copies one entry out of the archive, returning its name in the archive and its file name.
+/// directories, hidden files and macOS metadata are skipped without a report.
+fn extract_entry(
+    zip: &mut ZipArchive<File>,
+    index: usize,
+    server_config: &ServerConfig,
+    shared_file_names: &HashSet<String>,
+) -> Option<(String, ExtractedEntry)> {
+    let mut entry = match zip.by_index(index) {
+        Ok(entry) => entry,
+        Err(e) => {
+            return Some((
+                format!("entry {index}"),
+                Err(MusicUploaderError::ConstraintViolation(format!(
+                    "could not read entry: {e}"
+                ))),
+            ))
+        }
+    };
+    let name = entry.name().to_string();
+    if entry.is_dir() {
+        return None;
+    }
+    let Some(enclosed_name) = entry.enclosed_name() else {
+        return Some((
+            name,
+            Err(MusicUploaderError::ConstraintViolation(
+                "entry points outside of the archive".to_string(),
+            )),
+        ));
+    };
+    if is_ignored(&enclosed_name) {
+        println!("skipping archive entry {name}");
+        return None;
+    }
+    let file_name = get_entry_file_name(&enclosed_name, shared_file_names);
+    let upload = copy_to_temp_file(
+        &mut entry,
+        server_config.max_mb.megabytes().as_u64(),
+        &server_config.temp_file_dir,
+    );
+    Some((name, upload.map(|upload| (file_name, upload))))
+}
+
+/// file names used by more than one entry, like the tracks of a multi disc album in `CD1` and `CD2`.
+fn find_shared_file_names<'a>(names: impl Iterator<Item = &'a str>) -> HashSet<String> {
+    let mut seen = HashSet::new();
+    names
+        .map(Path::new)
+        .filter(|path| !is_ignored(path))
+        .filter_map(|path| path.file_name().and_then(OsStr::to_str))
+        .filter(|file_name| !seen.insert(file_name.to_string()))
+        .map(str::to_string)
+        .collect()
+}
+
+/// folders are otherwise ignored, but a shared file name gets its folder's name in front of it
+/// so the songs don't land on each other.
+fn get_entry_file_name(path: &Path, shared_file_names: &HashSet<String>) -> String {
+    let file_name = path
+        .file_name()
+        .and_then(OsStr::to_str)
+        .unwrap_or_default()
+        .to_string();
+    let folder = path
+        .parent()
+        .and_then(Path::file_name)
+        .and_then(OsStr::to_str);
+    match folder {
+        Some(folder) if shared_file_names.contains(&file_name) => format!("{folder} {file_name}"),
+        _ => file_name,
+    }
+}
+
+fn is_ignored(path: &Path) -> bool {
+    path.components().any(|component| match component {
+        Component::Normal(part) => {
+            part == MACOS_METADATA_DIR || part.to_string_lossy().starts_with('.')
+        }
+        _ => false,
+    })
+}
+
+/// the same checks and steps as a single upload, with the archive's artist and album as headers.
+async fn add_archive_entry(
+    server_config: &ServerConfig,
+    quota: &Quota,
+    upload_declaration: &UploadDeclarationItem,
+    file_name: &str,
+    upload: StreamedUpload,
+) -> Result<(ArchiveEntryStatus, PathBuf), MusicUploaderError> {
+    let metrics = Metrics::new(&server_config.server_db_dir);
+    let song = &upload_declaration.song;
+    let header_song = SongFields::new(&song.artist, &song.album, file_name, None);
+    let header_path = build_path(server_config, &header_song).map_err(map_path_error)?;
+    if header_path.exists() {
+        return Err(MusicUploaderError::SongAlreadyExists);
+    }
+    check_not_duplicate(server_config, &metrics, &upload.hash)?;
+    quota.check_user(
+        &metrics,
+        &OperationalData::new(&server_config.server_operational_db_dir),
+        &upload_declaration.user,
+        upload.size,
+    )?;
+    let mut file = upload.open().map_err(|e| {
+        MusicUploaderError::InternalServerError(format!("Failed to read temp file: {e}"))
+    })?;
+    validate_file_content(&header_path, &mut file)?;
+    let song_path = resolve_song_path(
+        server_config,
+        &song.artist,
+        &song.album,
+        file_name,
+        &upload,
+        false,
+    )
+    .await?;
+    if is_moderated(server_config, &upload_declaration.user) {
+        quarantine_song(server_config, upload, &song_path, &upload_declaration.user)?;
+        return Ok((ArchiveEntryStatus::Quarantined, song_path));
+    }
+    let size = upload.size;
+    let hash = upload.hash.clone();
+    persist_song(server_config, &metrics, upload, &song_path, None)?;
+    let _ = metrics.note_upload(
+        &song_path.to_string_lossy().to_string(),
+        &upload_declaration.user,
+        size,
+        &hash,
+    );
+    extract_cover_art(server_config, &song_path);
+    Ok((ArchiveEntryStatus::Accepted, song_path))
+}
+
+fn build_report(
+    server_config: &ServerConfig,
+    name: String,
+    result: Result<(ArchiveEntryStatus, PathBuf), MusicUploaderError>,
+) -> ArchiveEntryReport {
+    let (status, detail) = match result {
+        Ok((status, song_path)) => (status, get_library_path(server_config, &song_path)),
+        Err(
+            e @ (MusicUploaderError::SongAlreadyExists | MusicUploaderError::DuplicateContent(_)),
+        ) => (ArchiveEntryStatus::SkippedDuplicate, e.to_string()),
+        Err(e) => (ArchiveEntryStatus::Rejected, e.to_string()),
+    };
+    ArchiveEntryReport {
+        name,
+        status,
+        detail,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_metadata_is_ignored() {
+        assert!(is_ignored(Path::new("__MACOSX/album/._01 song.mp3")));
+        assert!(is_ignored(Path::new("album/.DS_Store")));
+        assert!(!is_ignored(Path::new("album/01 song.mp3")));
+    }
+
+    #[test]
+    fn test_shared_file_names_keep_their_folder() {
+        let names = [
+            "Album/CD1/01 Intro.mp3",
+            "Album/CD2/01 Intro.mp3",
+            "Album/CD1/02 Song.mp3",
+            "__MACOSX/Album/CD1/02 Song.mp3",
+        ];
+        let shared = find_shared_file_names(names.into_iter());
+        assert_eq!(HashSet::from(["01 Intro.mp3".to_string()]), shared);
+        let file_names = names[..3]
+            .iter()
+            .map(|name| get_entry_file_name(Path::new(name), &shared))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec!["CD1 01 Intro.mp3", "CD2 01 Intro.mp3", "02 Song.mp3"],
+            file_names
+        );
+    }
+}
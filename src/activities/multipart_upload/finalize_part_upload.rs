@@ -1,41 +1,109 @@
-use std::{fs, path::Path};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 use rocket::State;
 
 use crate::{
+    activities::upload::{check_not_duplicate, resolve_song_path},
+    authenticated::Authenticated,
     config::server_config::ServerConfig,
-    data::operational_data::{OperationalData, UploadDeclarationItem, UploadPartItem},
-    data_validation::{check_hash, read_bytes_from_file, write_bytes_to_new_file},
+    content_sniffing::validate_file_content,
+    cover_art::extract_cover_art,
+    data::{
+        metrics::Metrics,
+        operational_data::{OperationalData, UploadDeclarationItem, UploadPartItem},
+    },
+    data_validation::{check_hash, concatenate_to_temp_file},
     model::MusicUploaderError,
+    quarantine::{is_moderated, quarantine_song},
+    quota::Quota,
+    replace::persist_song,
+    scan::request_song_scan,
 };
 
+/// returns where the song was written in the library, None if it went to quarantine.
 pub async fn finalize_part_upload(
     upload_declaration: UploadDeclarationItem,
     server_config: &State<ServerConfig>,
     operational_data: OperationalData,
-) -> Result<(), MusicUploaderError> {
+    quota: &Quota,
+    replace_as: Option<&Authenticated>,
+) -> Result<Option<PathBuf>, MusicUploaderError> {
     let mut parts = get_parts(&upload_declaration.key, &operational_data)?;
     parts.sort();
     let base_path = Path::new(&server_config.temp_file_dir);
-    let bytes = parts
+    let part_paths = parts
         .iter()
-        .flat_map(|part| {
-            let expected_size = upload_declaration.get_expected_index_size(part.index) as usize;
-            let part_path = base_path.join(part.part_file_name());
-            read_bytes_from_file(part_path, expected_size)
-        })
-        .flat_map(|bytes| bytes)
+        .map(|part| base_path.join(part.part_file_name()))
         .collect::<Vec<_>>();
-    if upload_declaration.declared_size != bytes.len() as u32 {
+    let upload = concatenate_to_temp_file(&part_paths, &server_config.temp_file_dir)?;
+    if upload_declaration.declared_size as u64 != upload.size {
         return Err(MusicUploaderError::ConstraintViolation(
             "total file is not the expected size".to_string(),
         ));
     }
-    check_hash(&upload_declaration.hash, &bytes)?;
+    check_hash(&upload_declaration.hash, &upload.hash)?;
+    let declared_path = PathBuf::from(&upload_declaration.path);
+    let mut file = upload.open().map_err(|e| {
+        MusicUploaderError::InternalServerError(format!("Failed to read temp file: {e}"))
+    })?;
+    validate_file_content(&declared_path, &mut file)?;
+    // other uploads may have finished since this one was declared.
+    let metrics = Metrics::new(&server_config.server_db_dir);
+    quota.check_user(
+        &metrics,
+        &operational_data,
+        &upload_declaration.user,
+        upload.size,
+    )?;
+    if let Err(e) = check_not_duplicate(server_config, &metrics, &upload.hash) {
+        cleanup_upload(&upload_declaration.key, &operational_data, server_config)?;
+        return Err(e);
+    }
     // now we have verified everything. write to disk.
-    write_bytes_to_new_file(upload_declaration.path.into(), &bytes)?;
+    let song = &upload_declaration.song;
+    let final_path = match song.file_name.is_empty() {
+        // declared before the song headers were stored, so the tags can't be applied.
+        true => declared_path,
+        false => {
+            let resolved = resolve_song_path(
+                server_config,
+                &song.artist,
+                &song.album,
+                &song.file_name,
+                &upload,
+                replace_as.is_some(),
+            )
+            .await;
+            if let Err(MusicUploaderError::SongAlreadyExists) = resolved {
+                // the tags pointed at a song we already have, nothing left to retry.
+                cleanup_upload(&upload_declaration.key, &operational_data, server_config)?;
+            }
+            resolved?
+        }
+    };
+    if is_moderated(server_config, &upload_declaration.user) {
+        quarantine_song(server_config, upload, &final_path, &upload_declaration.user)?;
+        cleanup_upload(&upload_declaration.key, &operational_data, server_config)?;
+        return Ok(None);
+    }
+    let size = upload.size;
+    let hash = upload.hash.clone();
+    let replaced = persist_song(server_config, &metrics, upload, &final_path, replace_as)?;
+    extract_cover_art(server_config, &final_path);
+    let _ = metrics.note_upload(
+        &final_path.to_string_lossy().to_string(),
+        &upload_declaration.user,
+        size,
+        &hash,
+    );
     cleanup_upload(&upload_declaration.key, &operational_data, server_config)?;
-    Ok(())
+    if !replaced.is_empty() {
+        request_song_scan(server_config, &final_path, "the replaced songs");
+    }
+    Ok(Some(final_path))
 }
 
 /// deletes all temp file parts and metadata about the uplaod from the operational data tables.
@@ -44,8 +112,8 @@ pub fn cleanup_upload(
     operational_data: &OperationalData,
     server_config: &State<ServerConfig>,
 ) -> Result<(), MusicUploaderError> {
-    operational_data.cleanup_upload(key);
     let parts = get_parts(key, operational_data)?;
+    operational_data.cleanup_upload(key);
     let base_path = Path::new(&server_config.temp_file_dir);
     parts.iter().for_each(|part| {
         let part_path = base_path.join(part.part_file_name());
@@ -55,7 +123,7 @@ pub fn cleanup_upload(
     Ok(())
 }
 
-fn get_parts(
+pub fn get_parts(
     key: &String,
     operational_data: &OperationalData,
 ) -> Result<Vec<UploadPartItem>, MusicUploaderError> {
@@ -1,3 +1,5 @@
+pub mod declare_archive_upload;
 pub mod declare_upload;
+pub mod finalize_archive_upload;
 pub mod finalize_part_upload;
 pub mod upload_part;
@@ -8,10 +8,10 @@ use rocket::{
 };
 
 use crate::{
-    authenticated::Authenticated,
+    authenticated::Uploader,
     config::server_config::ServerConfig,
     data::operational_data::OperationalData,
-    data_validation::{check_hash, read_in_complete_data, write_bytes_to_new_file},
+    data_validation::{check_hash, stream_to_temp_file},
     model::{HeaderError, MusicUploaderError},
     rocket_utils::get_header_value,
 };
@@ -25,7 +25,7 @@ pub struct UploadPartHeaders {
 
 #[post("/uploadpart", data = "<data>")]
 pub async fn upload_part(
-    auth: Authenticated,
+    auth: Uploader,
     server_config: &State<ServerConfig>,
     headers: UploadPartHeaders,
     data: Data<'_>,
@@ -62,14 +62,19 @@ async fn upload_part_inner(
         ));
     }
     // step 2 load the data, validate that it is an acceptable size.  It should be "expected size length".
-    let bytes = read_in_complete_data(data, server_config.max_mb.megabytes()).await?;
-    if upload_declaration.get_expected_index_size(headers.index as u32) != bytes.len() as u32 {
+    let upload = stream_to_temp_file(
+        data,
+        server_config.max_mb.megabytes(),
+        &server_config.temp_file_dir,
+    )
+    .await?;
+    if upload_declaration.get_expected_index_size(headers.index as u32) as u64 != upload.size {
         return Err(MusicUploaderError::ConstraintViolation(
             "uploaded part is not expected size".to_string(),
         ));
     }
     // step 3 validate the hash
-    check_hash(&headers.part_hash, &bytes)?;
+    check_hash(&headers.part_hash, &upload.hash)?;
     // step 4: update the database.
     let part = operational_data
         .add_part(&headers.key, headers.index as u32, &headers.part_hash)
@@ -78,7 +83,7 @@ async fn upload_part_inner(
         ))?;
     // write the data to a file
     let file_path = Path::new(&server_config.temp_file_dir).join(part.part_file_name());
-    write_bytes_to_new_file(file_path, &bytes)
+    upload.persist(&file_path)
 }
 
 #[rocket::async_trait]
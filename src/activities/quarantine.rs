@@ -0,0 +1,171 @@
+use std::path::Path;
+
+use rocket::{
+    fs::NamedFile,
+    get, http, post,
+    request::{self, FromRequest},
+    Request, State,
+};
+
+use crate::{
+    authenticated::{Admin, Uploader},
+    config::server_config::ServerConfig,
+    data::{
+        metrics::Metrics,
+        operational_data::{OperationalData, QuarantinedUploadItem, QUARANTINE_PENDING},
+    },
+    model::{HeaderError, ListedQuarantinedUpload, MusicUploaderError, QuarantineResponse},
+    path_utils::get_library_path,
+    quarantine::{approve_upload, reject_upload},
+    rocket_utils::{get_header_value, get_optional_header_value},
+    scan::request_song_scan,
+};
+
+pub struct ReviewUploadHeaders {
+    id: i64,
+    reason: Option<String>,
+}
+
+/// uploads waiting for an admin to approve them.
+#[get("/quarantine")]
+pub async fn list_quarantine(
+    auth: Admin,
+    server_config: &State<ServerConfig>,
+) -> Result<QuarantineResponse, MusicUploaderError> {
+    println!("{} is reviewing quarantined uploads", auth.username);
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let uploads = operational_data.get_pending_quarantined_uploads().ok_or(
+        MusicUploaderError::InternalServerError("Failed to get quarantined uploads".to_string()),
+    )?;
+    metric(&server_config.server_db_dir, "quarantine", &auth.username);
+    Ok(build_response(server_config, uploads))
+}
+
+/// the user's own quarantined uploads, with the reason for any that were rejected.
+#[get("/myquarantine")]
+pub async fn my_quarantine(
+    auth: Uploader,
+    server_config: &State<ServerConfig>,
+) -> Result<QuarantineResponse, MusicUploaderError> {
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let uploads = operational_data
+        .get_user_quarantined_uploads(&auth.username)
+        .ok_or(MusicUploaderError::InternalServerError(
+            "Failed to get quarantined uploads".to_string(),
+        ))?;
+    metric(&server_config.server_db_dir, "myQuarantine", &auth.username);
+    let mut response = build_response(server_config, uploads);
+    response
+        .uploads
+        .iter_mut()
+        .for_each(|upload| upload.listen = None);
+    Ok(response)
+}
+
+#[get("/quarantinelisten?<id>")]
+pub async fn listen_to_quarantined_upload(
+    auth: Admin,
+    server_config: &State<ServerConfig>,
+    id: i64,
+) -> Result<NamedFile, MusicUploaderError> {
+    println!("{} is listening to quarantined upload {id}", auth.username);
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let upload = operational_data
+        .get_quarantined_upload(id)
+        .filter(|upload| upload.status == QUARANTINE_PENDING)
+        .ok_or(MusicUploaderError::ConstraintViolation(format!(
+            "no pending upload {id}"
+        )))?;
+    NamedFile::open(&upload.quarantine_path).await.map_err(|e| {
+        MusicUploaderError::InternalServerError(format!(
+            "Failed to open quarantined upload {id}: {e}"
+        ))
+    })
+}
+
+#[post("/approveupload")]
+pub async fn approve_quarantined_upload(
+    auth: Admin,
+    server_config: &State<ServerConfig>,
+    headers: ReviewUploadHeaders,
+) -> Result<String, MusicUploaderError> {
+    let song_path = approve_upload(server_config, headers.id, &auth.username)?;
+    metric(
+        &server_config.server_db_dir,
+        "approveUpload",
+        &auth.username,
+    );
+    request_song_scan(server_config, &song_path, "the approved song");
+    Ok(format!(
+        "approved upload {}: {}",
+        headers.id,
+        get_library_path(server_config, &song_path)
+    ))
+}
+
+#[post("/rejectupload")]
+pub async fn reject_quarantined_upload(
+    auth: Admin,
+    server_config: &State<ServerConfig>,
+    headers: ReviewUploadHeaders,
+) -> Result<String, MusicUploaderError> {
+    let reason = headers
+        .reason
+        .filter(|reason| !reason.trim().is_empty())
+        .ok_or(MusicUploaderError::ConstraintViolation(
+            "a reason is needed to reject an upload".to_string(),
+        ))?;
+    reject_upload(server_config, headers.id, &auth.username, reason.trim())?;
+    metric(&server_config.server_db_dir, "rejectUpload", &auth.username);
+    Ok(format!("rejected upload {}", headers.id))
+}
+
+fn build_response(
+    server_config: &ServerConfig,
+    uploads: Vec<QuarantinedUploadItem>,
+) -> QuarantineResponse {
+    let uploads = uploads
+        .into_iter()
+        .map(|upload| ListedQuarantinedUpload {
+            id: upload.id,
+            path: get_library_path(server_config, Path::new(&upload.song_path)),
+            user: upload.user,
+            size: upload.size,
+            artist: upload.artist,
+            album: upload.album,
+            title: upload.title,
+            listen: (upload.status == QUARANTINE_PENDING)
+                .then(|| format!("/api/quarantinelisten?id={}", upload.id)),
+            status: upload.status,
+            reason: upload.reason,
+            timestamp: upload.timestamp,
+        })
+        .collect();
+    QuarantineResponse { uploads }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ReviewUploadHeaders {
+    type Error = HeaderError;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match Self::from_request_inner(req).await {
+            Ok(a) => request::Outcome::Success(a),
+            Err(e) => request::Outcome::Error((http::Status::BadRequest, e)),
+        }
+    }
+}
+
+impl<'r> ReviewUploadHeaders {
+    async fn from_request_inner(req: &'r Request<'_>) -> Result<Self, HeaderError> {
+        let headers = req.headers();
+        Ok(Self {
+            id: get_header_value(headers, "id")?,
+            reason: get_optional_header_value(headers, "reason")?,
+        })
+    }
+}
+
+fn metric(db_path: &String, route: &str, user: &String) {
+    let metrics = Metrics::new(db_path);
+    let _ = metrics.note_route(&route.to_string(), user);
+}
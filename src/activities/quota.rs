@@ -0,0 +1,24 @@
+use rocket::{get, State};
+
+use crate::{
+    authenticated::{Authenticated, Authenticator},
+    config::server_config::ServerConfig,
+    data::{metrics::Metrics, operational_data::OperationalData},
+    model::{MusicUploaderError, QuotaResponse},
+    quota::Quota,
+};
+
+#[get("/quota")]
+pub async fn get_quota(
+    auth: Authenticated,
+    authenticator: &State<Authenticator>,
+    server_config: &State<ServerConfig>,
+) -> Result<QuotaResponse, MusicUploaderError> {
+    println!("{} is checking their quota", auth.username);
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let quota = authenticator.get_quota(server_config, &operational_data, &auth.username);
+    let metrics = Metrics::new(&server_config.server_db_dir);
+    let usage = Quota::get_usage(&metrics, &operational_data, &auth.username)?;
+    let _ = metrics.note_route(&"quota".to_string(), &auth.username);
+    Ok(quota.build_response(usage))
+}
@@ -0,0 +1,33 @@
+use rocket::{post, tokio, State};
+
+use crate::{
+    authenticated::{Admin, Authenticator},
+    config::server_config::ServerConfig,
+    data::metrics::Metrics,
+    model::MusicUploaderError,
+};
+
+#[post("/reloadusers")]
+pub async fn reload_users(
+    auth: Admin,
+    authenticator: &State<Authenticator>,
+    server_config: &State<ServerConfig>,
+) -> Result<String, MusicUploaderError> {
+    println!("{} is reloading users", auth.username);
+    let reloading_authenticator = authenticator.inner().clone();
+    let result = tokio::task::spawn_blocking(move || reloading_authenticator.reload())
+        .await
+        .map_err(|e| MusicUploaderError::InternalServerError(e.to_string()))?
+        .map(|num_users| format!("loaded {num_users} users"))
+        .map_err(|e| {
+            println!("keeping the previous users, failed to reload: {e}");
+            MusicUploaderError::ConstraintViolation(e.to_string())
+        });
+    metric(&server_config.server_db_dir, &auth.username);
+    result
+}
+
+fn metric(db_path: &String, user: &String) {
+    let metrics = Metrics::new(db_path);
+    let _ = metrics.note_route(&"reloadUsers".to_string(), user);
+}
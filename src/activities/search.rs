@@ -50,8 +50,9 @@ pub async fn album_search(
     let response = AlbumSearchResponse {
         album: found_album.get_title().to_string(),
         uploader: match upload_result {
-            Some(upload_result) => upload_result.user,
-            None => {
+            // songs that were already in the library before we tracked them have no uploader.
+            Some(upload_result) if !upload_result.user.is_empty() => upload_result.user,
+            _ => {
                 println!(
                     "there is no music upload data for ({})",
                     found_album.get_title()
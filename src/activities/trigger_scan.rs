@@ -1,38 +1,93 @@
 use crate::{
-    authenticated::Authenticated, clients::plex_client::PlexClient,
-    config::server_config::ServerConfig, data::metrics::Metrics, model::MusicUploaderError,
+    authenticated::{Admin, Uploader},
+    config::server_config::ServerConfig,
+    data::metrics::Metrics,
+    model::{HeaderError, MusicUploaderError, ScanQueueResponse},
+    path_utils::{find_album_dir, get_library_path},
+    rocket_utils::get_optional_header_value,
+    scan::{get_scan_queue, request_scan},
 };
-use rocket::{post, State};
+use rocket::{
+    get, http, post,
+    request::{self, FromRequest},
+    Request, State,
+};
+
+/// without an artist and album the whole library is scanned.
+pub struct TriggerScanHeaders {
+    artist: Option<String>,
+    album: Option<String>,
+}
 
 #[post("/triggerscan")]
 pub async fn trigger_scan(
-    auth: Authenticated,
+    auth: Uploader,
     server_config: &State<ServerConfig>,
+    headers: TriggerScanHeaders,
 ) -> Result<String, MusicUploaderError> {
     println!("{} is triggering a scan", auth.username);
-    let plex_client = PlexClient::new(
-        &server_config.plex_url,
-        server_config.plex_server_token.clone(),
+    let metrics = Metrics::new(&server_config.server_db_dir);
+    let dirs = match (&headers.artist, &headers.album) {
+        // plex quietly scans nothing when asked for a directory that doesn't exist.
+        (Some(artist), Some(album)) => vec![find_album_dir(server_config, &metrics, artist, album)
+            .ok_or(MusicUploaderError::ConstraintViolation(format!(
+                "there is no album directory for {album} by {artist}"
+            )))?],
+        (None, None) => Vec::new(),
+        _ => {
+            return Err(MusicUploaderError::ConstraintViolation(
+                "an album scan needs both the artist and album headers".to_string(),
+            ))
+        }
+    };
+    request_scan(
+        server_config,
+        &dirs,
+        &format!("requested by {}", auth.username),
     );
-    let result = plex_client
-        .trigger_scan(server_config.plex_music_library_id)
-        .await
-        .map(|_| {
-            println!("plex successfully scanned :)");
-            "successful scan".to_string()
+    metric(&server_config.server_db_dir, "triggerScan", &auth.username);
+    let scope = dirs
+        .first()
+        .map(|dir| get_library_path(server_config, dir))
+        .unwrap_or("the whole library".to_string());
+    Ok(format!(
+        "scan of {scope} queued, plex will be asked within {} seconds",
+        server_config.scan_debounce_seconds
+    ))
+}
+
+#[get("/scanqueue")]
+pub async fn scan_queue(
+    auth: Admin,
+    server_config: &State<ServerConfig>,
+) -> Result<ScanQueueResponse, MusicUploaderError> {
+    let queue = get_scan_queue(server_config)?;
+    metric(&server_config.server_db_dir, "scanQueue", &auth.username);
+    Ok(queue)
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TriggerScanHeaders {
+    type Error = HeaderError;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match Self::from_request_inner(req).await {
+            Ok(a) => request::Outcome::Success(a),
+            Err(e) => request::Outcome::Error((http::Status::BadRequest, e)),
+        }
+    }
+}
+
+impl<'r> TriggerScanHeaders {
+    async fn from_request_inner(req: &'r Request<'_>) -> Result<Self, HeaderError> {
+        let headers = req.headers();
+        Ok(Self {
+            artist: get_optional_header_value(headers, "artist")?,
+            album: get_optional_header_value(headers, "album")?,
         })
-        .map_err(|e| {
-            println!(
-                "there was an error reaching out to plex to trigger scan: {}",
-                e.to_string()
-            );
-            MusicUploaderError::InternalServerError(e.to_string())
-        });
-    metric(&server_config.server_db_dir, &auth.username);
-    result
+    }
 }
 
-fn metric(db_path: &String, user: &String) {
+fn metric(db_path: &String, route: &str, user: &String) {
     let metrics = Metrics::new(db_path);
-    let _ = metrics.note_route(&"triggerScan".to_string(), user);
+    let _ = metrics.note_route(&route.to_string(), user);
 }
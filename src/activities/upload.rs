@@ -1,22 +1,39 @@
 use std::fmt;
+use std::path::{Path, PathBuf};
 
 use rocket::data::{Data, ToByteUnit};
 use rocket::request::FromRequest;
 use rocket::{http, post, request, Request, State};
 
-use crate::authenticated::Authenticated;
+use crate::authenticated::{Authenticated, Authenticator, Uploader};
+use crate::batch::{check_batch_file, finish_batch_file, skip_batch_file};
 use crate::config::server_config::ServerConfig;
+use crate::content_sniffing::validate_file_content;
+use crate::cover_art::extract_cover_art;
 use crate::data::metrics::Metrics;
-use crate::data_validation::{check_hash, read_in_complete_data, write_bytes_to_new_file};
+use crate::data::operational_data::OperationalData;
+use crate::data_validation::{check_hash, stream_to_temp_file, StreamedUpload};
 use crate::model::{HeaderError, MusicUploaderError};
-use crate::path_utils::{build_and_validate_path, ValidateDirectoryError};
-use crate::rocket_utils::get_header_value;
+use crate::path_template::SongFields;
+use crate::path_utils::{
+    build_and_create_path, build_and_validate_path, build_path, get_library_path,
+    ValidateDirectoryError,
+};
+use crate::quarantine::{check_not_quarantined, is_moderated, quarantine_song};
+use crate::quota::Quota;
+use crate::replace::{check_can_replace, find_replaced_songs, persist_song};
+use crate::rocket_utils::{get_header_value, get_optional_header_value};
+use crate::scan::request_song_scan;
+use crate::tags::resolve_artist_album;
 
 pub struct UploadHeaders {
     hash: String,
     file_name: String,
     album: String,
     artist: String,
+    content_length: Option<u64>,
+    replace: bool,
+    batch: Option<String>,
 }
 
 impl fmt::Debug for UploadHeaders {
@@ -25,19 +42,42 @@ impl fmt::Debug for UploadHeaders {
             .field("file_name", &self.file_name)
             .field("album", &self.album)
             .field("artist", &self.artist)
+            .field("replace", &self.replace)
+            .field("batch", &self.batch)
             .finish()
     }
 }
 
 #[post("/upload", data = "<data>")]
 pub async fn upload(
-    auth: Authenticated,
+    auth: Uploader,
+    authenticator: &State<Authenticator>,
     server_config: &State<ServerConfig>,
     headers: UploadHeaders,
     data: Data<'_>,
 ) -> Result<String, MusicUploaderError> {
     println!("\n{} is trying to upload {:?}", &auth.username, headers);
-    match upload_inner(server_config, headers, data, &auth.username).await {
+    let quota = authenticator.get_quota(
+        server_config,
+        &OperationalData::new(&server_config.server_operational_db_dir),
+        &auth.username,
+    );
+    let replace_as = headers.replace.then_some(&*auth);
+    let batch = headers.batch.clone();
+    let file_name = headers.file_name.clone();
+    let result = upload_inner(
+        server_config,
+        headers,
+        data,
+        &auth.username,
+        &quota,
+        replace_as,
+    )
+    .await;
+    if let (Some(batch), Err(e)) = (&batch, &result) {
+        skip_batch_file(server_config, batch, &file_name, e);
+    }
+    match result {
         Ok(x) => {
             println!("success :3");
             Ok(x)
@@ -46,6 +86,10 @@ pub async fn upload(
             println!("Assuming success since song already existed");
             Ok(MusicUploaderError::SongAlreadyExists.to_string())
         }
+        Err(e @ MusicUploaderError::DuplicateContent(_)) => {
+            println!("Assuming success since the same song is already in the library");
+            Ok(e.to_string())
+        }
         Err(e) => {
             println!("error: {}", e.to_string());
             Err(e)
@@ -58,31 +102,169 @@ async fn upload_inner(
     headers: UploadHeaders,
     data: Data<'_>,
     username: &String,
+    quota: &Quota,
+    replace_as: Option<&Authenticated>,
 ) -> Result<String, MusicUploaderError> {
-    let dir = build_and_validate_path(
+    if let Some(batch) = &headers.batch {
+        check_batch_file(server_config, batch, username, &headers.file_name)?;
+    }
+    // the tags can still move the song, but there is no point reading the body for a song we have.
+    let header_song = SongFields::new(&headers.artist, &headers.album, &headers.file_name, None);
+    let header_path = build_path(server_config, &header_song).map_err(map_path_error)?;
+    let metrics = Metrics::new(&server_config.server_db_dir);
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    check_can_upload_to(server_config, &metrics, &header_path, replace_as)?;
+    check_not_duplicate(server_config, &metrics, &headers.hash)?;
+    quota.check_user(
+        &metrics,
+        &operational_data,
+        username,
+        headers.content_length.unwrap_or(0),
+    )?;
+    let upload = stream_to_temp_file(
+        data,
+        server_config.max_mb.megabytes(),
+        &server_config.temp_file_dir,
+    )
+    .await?;
+    quota.check_user(&metrics, &operational_data, username, upload.size)?;
+    check_hash(&headers.hash, &upload.hash)?;
+    let mut file = upload.open().map_err(|e| {
+        MusicUploaderError::InternalServerError(format!("Failed to read temp file: {e}"))
+    })?;
+    validate_file_content(&header_path, &mut file)?;
+    let dir = resolve_song_path(
         server_config,
         &headers.artist,
         &headers.album,
         &headers.file_name,
+        &upload,
+        replace_as.is_some(),
     )
-    .await
-    .map_err(|e| match e {
-        ValidateDirectoryError::FileAlreadyExists => MusicUploaderError::SongAlreadyExists,
-        e => MusicUploaderError::ValidateDirectoryError(Box::new(e)),
-    })?;
+    .await?;
+    if is_moderated(server_config, username) {
+        let id = quarantine_song(server_config, upload, &dir, username)?;
+        if let Some(batch) = &headers.batch {
+            finish_batch_file(server_config, batch, &headers.file_name, None);
+        }
+        let _ = metrics.note_route(&"upload".to_string(), username);
+        return Ok(format!(
+            "uploaded file: {}, it will be added once an admin approves upload {id}",
+            headers.file_name
+        ));
+    }
     let dir_str = dir.to_str().unwrap_or("<no dir?>").to_string();
     println!("using directory: {}", &dir_str);
-    let bytes = read_in_complete_data(data, server_config.max_mb.megabytes()).await?;
-    check_hash(&headers.hash, &bytes)?;
-    write_bytes_to_new_file(dir, &bytes)?;
-    metric(&server_config.server_db_dir, &dir_str, username);
-    Ok(format!("uploaded file: {}", headers.file_name))
+    let size = upload.size;
+    let hash = upload.hash.clone();
+    let replaced = persist_song(server_config, &metrics, upload, &dir, replace_as)?;
+    metric(&metrics, &dir_str, username, size, &hash);
+    extract_cover_art(server_config, &dir);
+    if let Some(batch) = &headers.batch {
+        finish_batch_file(server_config, batch, &headers.file_name, dir.parent());
+    }
+    if replaced.is_empty() {
+        return Ok(format!("uploaded file: {}", headers.file_name));
+    }
+    request_song_scan(server_config, &dir, "the replaced songs");
+    let replaced = replaced
+        .iter()
+        .map(|song| get_library_path(server_config, song))
+        .collect::<Vec<_>>();
+    Ok(format!(
+        "uploaded file: {}, replaced {}",
+        headers.file_name,
+        replaced.join(", ")
+    ))
+}
+
+/// fails early for a song that is already in the library, unless it is being replaced by someone
+/// who is allowed to.
+pub fn check_can_upload_to(
+    server_config: &ServerConfig,
+    metrics: &Metrics,
+    song_path: &Path,
+    replace_as: Option<&Authenticated>,
+) -> Result<(), MusicUploaderError> {
+    match replace_as {
+        Some(auth) if is_moderated(server_config, &auth.username) => {
+            Err(MusicUploaderError::ConstraintViolation(
+                "moderated users can't replace songs".to_string(),
+            ))
+        }
+        Some(auth) => check_can_replace(
+            server_config,
+            metrics,
+            auth,
+            &find_replaced_songs(server_config, song_path),
+        ),
+        None if song_path.exists() => Err(MusicUploaderError::SongAlreadyExists),
+        None => Ok(()),
+    }
+}
+
+/// applies the tag_policy to a verified upload and creates the directories for where it ends up.
+/// when replacing, a song may already be at that path.
+pub async fn resolve_song_path(
+    server_config: &ServerConfig,
+    artist: &String,
+    album: &String,
+    file_name: &str,
+    upload: &StreamedUpload,
+    replace: bool,
+) -> Result<PathBuf, MusicUploaderError> {
+    let tags = upload.read_tags();
+    let (artist, album) =
+        resolve_artist_album(server_config.tag_policy, artist, album, tags.as_ref())?;
+    let song = SongFields::new(&artist, &album, file_name, tags.as_ref());
+    match replace {
+        true => build_and_create_path(server_config, &song).await,
+        false => build_and_validate_path(server_config, &song).await,
+    }
+    .map_err(map_path_error)
+}
+
+/// fails with the library path of a song that has the same contents, if there is one still on disk
+/// or waiting in quarantine.
+pub fn check_not_duplicate(
+    server_config: &ServerConfig,
+    metrics: &Metrics,
+    hash: &str,
+) -> Result<(), MusicUploaderError> {
+    match find_song_by_hash(metrics, hash)? {
+        Some(song_path) => Err(MusicUploaderError::DuplicateContent(get_library_path(
+            server_config,
+            &song_path,
+        ))),
+        None => check_not_quarantined(server_config, hash),
+    }
+}
+
+/// the song in the library with these contents.
+pub fn find_song_by_hash(
+    metrics: &Metrics,
+    hash: &str,
+) -> Result<Option<PathBuf>, MusicUploaderError> {
+    let uploads = metrics.get_uploads_by_hash(&hash.to_lowercase()).ok_or(
+        MusicUploaderError::InternalServerError("failed to look up uploads by hash".to_string()),
+    )?;
+    // the file may have been deleted or moved outside of music uploader.
+    Ok(uploads
+        .into_iter()
+        .map(|upload| PathBuf::from(upload.path))
+        .find(|song_path| song_path.exists()))
+}
+
+pub fn map_path_error(e: ValidateDirectoryError) -> MusicUploaderError {
+    match e {
+        ValidateDirectoryError::FileAlreadyExists => MusicUploaderError::SongAlreadyExists,
+        e => MusicUploaderError::ValidateDirectoryError(Box::new(e)),
+    }
 }
 
-fn metric(db_path: &String, song_path: &String, user: &String) {
-    let metrics = Metrics::new(db_path);
+fn metric(metrics: &Metrics, song_path: &String, user: &String, bytes: u64, hash: &str) {
     let _ = metrics.note_route(&"upload".to_string(), user);
-    let _ = metrics.note_upload(song_path, user);
+    let _ = metrics.note_upload(song_path, user, bytes, hash);
 }
 
 #[rocket::async_trait]
@@ -104,6 +286,9 @@ impl<'r> UploadHeaders {
             file_name: get_header_value(headers, "file")?,
             album: get_header_value(headers, "album")?,
             artist: get_header_value(headers, "artist")?,
+            content_length: get_optional_header_value(headers, "Content-Length")?,
+            replace: get_optional_header_value(headers, "replace")?.unwrap_or(false),
+            batch: get_optional_header_value(headers, "batch")?,
         })
     }
 }
@@ -0,0 +1,173 @@
+use crate::{
+    config::server_config::ServerConfig,
+    data::operational_data::{AliasItem, NewAlias, OperationalData, ALIAS_ALBUM, ALIAS_ARTIST},
+    model::{AliasResponse, ListedAlias, MusicUploaderError},
+    path_template::SongFields,
+    path_utils::get_match_key,
+};
+
+/// swaps the artist and album for their canonical names before the song's path is built.
+/// the artist goes first, so an album alias can be specific to the canonical artist.
+pub fn apply_aliases(server_config: &ServerConfig, song: &SongFields) -> SongFields {
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let mut song = song.clone();
+    if let Some(artist) = find_canonical_name(&operational_data, ALIAS_ARTIST, "", &song.artist) {
+        if song.album_artist == song.artist {
+            song.album_artist = artist.clone();
+        }
+        song.artist = artist;
+    }
+    let artist_key = get_match_key(&song.artist);
+    if let Some(album) =
+        find_canonical_name(&operational_data, ALIAS_ALBUM, &artist_key, &song.album)
+    {
+        song.album = album;
+    }
+    song
+}
+
+fn find_canonical_name(
+    operational_data: &OperationalData,
+    kind: &str,
+    artist_key: &str,
+    name: &str,
+) -> Option<String> {
+    let alias_key = get_match_key(name);
+    if alias_key.is_empty() {
+        return None;
+    }
+    let canonical = operational_data
+        .get_canonical_names(kind, artist_key, &alias_key)?
+        .into_iter()
+        .next()?;
+    println!("filing the {kind} {name} under its canonical name {canonical}");
+    Some(canonical)
+}
+
+/// artist is only for album aliases, which apply to every artist without it.
+pub fn add_alias(
+    server_config: &ServerConfig,
+    kind: &str,
+    artist: Option<&str>,
+    alias: &str,
+    canonical: &str,
+    admin: &str,
+) -> Result<(), MusicUploaderError> {
+    let (artist, artist_key) = get_alias_artist(kind, artist)?;
+    let alias_key = get_match_key(alias);
+    let canonical = canonical.trim();
+    if alias_key.is_empty() || canonical.is_empty() {
+        return Err(MusicUploaderError::ConstraintViolation(
+            "an alias and its canonical name need letters or digits".to_string(),
+        ));
+    }
+    if alias_key == get_match_key(canonical) {
+        return Err(MusicUploaderError::ConstraintViolation(format!(
+            "{alias} and {canonical} already share a folder"
+        )));
+    }
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    // aliases are only followed once, so neither name can be part of another alias chain.
+    let chained = get_all_aliases(&operational_data)?
+        .into_iter()
+        .find(|existing| {
+            existing.kind == kind
+                && existing.artist_key == artist_key
+                && (existing.alias_key == get_match_key(canonical)
+                    || get_match_key(&existing.canonical) == alias_key)
+        });
+    if let Some(existing) = chained {
+        return Err(MusicUploaderError::ConstraintViolation(format!(
+            "{} is already an alias of {}",
+            existing.alias, existing.canonical
+        )));
+    }
+    let new_alias = NewAlias {
+        kind: kind.to_string(),
+        artist: artist.to_string(),
+        artist_key,
+        alias: alias.trim().to_string(),
+        alias_key,
+        canonical: canonical.to_string(),
+        created_by: admin.to_string(),
+    };
+    match operational_data.add_alias(&new_alias) {
+        Some(true) => {
+            println!("{admin} made the {kind} {alias} an alias of {canonical}");
+            Ok(())
+        }
+        Some(false) => Err(MusicUploaderError::ConstraintViolation(format!(
+            "{alias} is already an alias, remove it first"
+        ))),
+        None => Err(MusicUploaderError::InternalServerError(
+            "Failed to add alias".to_string(),
+        )),
+    }
+}
+
+pub fn remove_alias(
+    server_config: &ServerConfig,
+    kind: &str,
+    artist: Option<&str>,
+    alias: &str,
+    admin: &str,
+) -> Result<(), MusicUploaderError> {
+    let (_, artist_key) = get_alias_artist(kind, artist)?;
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    match operational_data.remove_alias(kind, &artist_key, &get_match_key(alias)) {
+        Some(true) => {
+            println!("{admin} removed the {kind} alias {alias}");
+            Ok(())
+        }
+        Some(false) => Err(MusicUploaderError::ConstraintViolation(format!(
+            "{alias} is not an alias"
+        ))),
+        None => Err(MusicUploaderError::InternalServerError(
+            "Failed to remove alias".to_string(),
+        )),
+    }
+}
+
+pub fn list_aliases(server_config: &ServerConfig) -> Result<AliasResponse, MusicUploaderError> {
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let aliases = get_all_aliases(&operational_data)?
+        .into_iter()
+        .map(|alias| ListedAlias {
+            kind: alias.kind,
+            artist: (!alias.artist.is_empty()).then_some(alias.artist),
+            alias: alias.alias,
+            canonical: alias.canonical,
+            created_by: alias.created_by,
+            timestamp: alias.timestamp,
+        })
+        .collect();
+    Ok(AliasResponse { aliases })
+}
+
+/// the artist an alias is limited to and its key, both empty when it isn't.
+fn get_alias_artist(
+    kind: &str,
+    artist: Option<&str>,
+) -> Result<(String, String), MusicUploaderError> {
+    match (kind, artist.map(str::trim)) {
+        (ALIAS_ARTIST, None) => Ok((String::new(), String::new())),
+        (ALIAS_ARTIST, Some(_)) => Err(MusicUploaderError::ConstraintViolation(
+            "only album aliases can be limited to an artist".to_string(),
+        )),
+        (ALIAS_ALBUM, None) => Ok((String::new(), String::new())),
+        (ALIAS_ALBUM, Some(artist)) => Ok((artist.to_string(), get_match_key(artist))),
+        _ => Err(MusicUploaderError::ConstraintViolation(format!(
+            "kind has to be {ALIAS_ARTIST} or {ALIAS_ALBUM}"
+        ))),
+    }
+}
+
+fn get_all_aliases(
+    operational_data: &OperationalData,
+) -> Result<Vec<AliasItem>, MusicUploaderError> {
+    operational_data
+        .get_aliases()
+        .ok_or(MusicUploaderError::InternalServerError(
+            "Failed to get aliases".to_string(),
+        ))
+}
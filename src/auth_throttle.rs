@@ -0,0 +1,192 @@
+use crate::{
+    config::server_config::ServerConfig,
+    data::operational_data::{AuthFailureSummary, OperationalData},
+    time_utils::get_now_timestamp,
+};
+
+const MAX_LOCKOUT_SECONDS: i64 = 60 * 60;
+
+/// tracks failed logins per username and per client ip.
+/// once either reaches the threshold within the window, further attempts are refused
+/// for a lockout that doubles with every additional failure.
+/// the failures are counted in the window before the last one, so a lockout keeps its length
+/// after its failures leave the window.
+pub struct AuthThrottle {
+    failure_threshold: u32,
+    failure_window_seconds: i64,
+    lockout_base_seconds: i64,
+}
+
+impl AuthThrottle {
+    pub fn new(server_config: &ServerConfig) -> Self {
+        Self {
+            failure_threshold: server_config.auth_failure_threshold,
+            failure_window_seconds: server_config.auth_failure_window_minutes as i64 * 60,
+            lockout_base_seconds: server_config.auth_lockout_base_seconds as i64,
+        }
+    }
+
+    /// returns how many seconds are left on a lockout, if there is one.
+    pub fn get_lockout(
+        &self,
+        operational_data: &OperationalData,
+        username: Option<&str>,
+        ip: &str,
+    ) -> Option<i64> {
+        self.get_lockout_at(operational_data, username, ip, get_now_timestamp())
+    }
+
+    fn get_lockout_at(
+        &self,
+        operational_data: &OperationalData,
+        username: Option<&str>,
+        ip: &str,
+        now: i64,
+    ) -> Option<i64> {
+        let since = now - self.get_retention_seconds();
+        let window = self.failure_window_seconds;
+        let user_summary = username.and_then(|username| {
+            operational_data.get_user_auth_failure_summary(username, since, window)
+        });
+        let ip_summary = operational_data.get_ip_auth_failure_summary(ip, since, window);
+        [user_summary, ip_summary]
+            .into_iter()
+            .flatten()
+            .filter_map(|summary| self.get_remaining_lockout_seconds(&summary, now))
+            .max()
+    }
+
+    pub fn note_failure(
+        &self,
+        operational_data: &OperationalData,
+        username: Option<&str>,
+        ip: &str,
+        reason: &str,
+    ) {
+        println!(
+            "failed login from {ip} for {}: {reason}",
+            username.unwrap_or("<no username>")
+        );
+        let now = get_now_timestamp();
+        operational_data.note_auth_failure(username.unwrap_or(""), ip, reason, now);
+        operational_data.prune_auth_failures(now - self.get_retention_seconds());
+    }
+
+    /// a failure older than this can neither start a lockout nor be the last one of a running lockout.
+    fn get_retention_seconds(&self) -> i64 {
+        self.failure_window_seconds.max(MAX_LOCKOUT_SECONDS)
+    }
+
+    fn get_remaining_lockout_seconds(&self, summary: &AuthFailureSummary, now: i64) -> Option<i64> {
+        let last_failure = summary.last_failure?;
+        if summary.count < self.failure_threshold {
+            return None;
+        }
+        let doublings = (summary.count - self.failure_threshold).min(16);
+        let lockout_seconds =
+            (self.lockout_base_seconds * 2_i64.pow(doublings)).min(MAX_LOCKOUT_SECONDS);
+        let remaining = last_failure + lockout_seconds - now;
+        match remaining > 0 {
+            true => Some(remaining),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn build_throttle() -> AuthThrottle {
+        AuthThrottle {
+            failure_threshold: 3,
+            failure_window_seconds: 15 * 60,
+            lockout_base_seconds: 30,
+        }
+    }
+
+    fn build_summary(count: u32, last_failure: i64) -> AuthFailureSummary {
+        AuthFailureSummary {
+            count,
+            last_failure: Some(last_failure),
+        }
+    }
+
+    #[test]
+    fn test_no_lockout_below_threshold() {
+        let throttle = build_throttle();
+        assert_eq!(
+            None,
+            throttle.get_remaining_lockout_seconds(&build_summary(2, 1000), 1000)
+        );
+    }
+
+    #[test]
+    fn test_lockout_doubles() {
+        let throttle = build_throttle();
+        assert_eq!(
+            Some(30),
+            throttle.get_remaining_lockout_seconds(&build_summary(3, 1000), 1000)
+        );
+        assert_eq!(
+            Some(60),
+            throttle.get_remaining_lockout_seconds(&build_summary(4, 1000), 1000)
+        );
+        assert_eq!(
+            Some(110),
+            throttle.get_remaining_lockout_seconds(&build_summary(5, 1000), 1010)
+        );
+    }
+
+    #[test]
+    fn test_lockout_outlasts_the_window() {
+        let throttle = AuthThrottle {
+            failure_threshold: 3,
+            failure_window_seconds: 15 * 60,
+            lockout_base_seconds: 10 * 60,
+        };
+        let db = OperationalData::new(&"./testDb.db".to_string());
+        let ip = format!("throttle test {}", rand::random::<u64>());
+        let now = get_now_timestamp();
+        for _ in 0..4 {
+            db.note_auth_failure("", &ip, "test", now);
+        }
+        // four failures lock out for 20 minutes, past the 15 minute window.
+        let after_window = now + throttle.failure_window_seconds + 1;
+        assert!(throttle
+            .get_lockout_at(&db, None, &ip, after_window)
+            .is_some_and(|remaining| remaining > 0 && remaining <= 5 * 60));
+        assert_eq!(
+            None,
+            throttle.get_lockout_at(&db, None, &ip, now + 20 * 60 + 1)
+        );
+    }
+
+    #[test]
+    fn test_spread_out_failures_do_not_lock_out() {
+        let throttle = build_throttle();
+        let db = OperationalData::new(&"./testDb.db".to_string());
+        let ip = format!("throttle test {}", rand::random::<u64>());
+        let now = get_now_timestamp();
+        // a typo every 10 minutes never puts 3 failures in the same 15 minutes.
+        for minutes_ago in [50, 40, 30, 20, 10, 0] {
+            db.note_auth_failure("", &ip, "test", now - minutes_ago * 60);
+        }
+        assert_eq!(None, throttle.get_lockout_at(&db, None, &ip, now));
+        db.note_auth_failure("", &ip, "test", now);
+        assert_eq!(Some(30), throttle.get_lockout_at(&db, None, &ip, now));
+    }
+
+    #[test]
+    fn test_lockout_expires_and_is_capped() {
+        let throttle = build_throttle();
+        assert_eq!(
+            None,
+            throttle.get_remaining_lockout_seconds(&build_summary(3, 1000), 1030)
+        );
+        assert_eq!(
+            Some(MAX_LOCKOUT_SECONDS),
+            throttle.get_remaining_lockout_seconds(&build_summary(100, 1000), 1000)
+        );
+    }
+}
@@ -1,4 +1,8 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{Arc, PoisonError, RwLock},
+};
 
 use rocket::{
     http,
@@ -8,18 +12,61 @@ use rocket::{
 use rocket_basicauth::BasicAuth;
 use thiserror::Error;
 
-use crate::config::{load_toml, secrets_config::Users};
+use crate::{
+    auth_throttle::AuthThrottle,
+    config::{
+        secrets_config::{Role, User, Users},
+        server_config::ServerConfig,
+        try_load_toml,
+    },
+    data::operational_data::OperationalData,
+    password_utils::{hash_password, hash_secret_token, is_valid_password_hash, verify_password},
+    plex_auth::PlexAuthenticator,
+    quota::Quota,
+    time_utils::get_now_timestamp,
+};
+
+const BEARER_PREFIX: &str = "Bearer ";
+const PLEX_TOKEN_HEADER: &str = "X-Plex-Token";
 
 pub struct Authenticated {
     pub username: String,
+    pub role: Role,
 }
 
+/// route guard for users with at least the uploader role.
+pub struct Uploader(pub Authenticated);
+
+/// route guard for users with the admin role.
+pub struct Admin(pub Authenticated);
+
+/// route guard that only accepts a username and password, for routes that hand out credentials.
+pub struct PasswordAuthenticated(pub Authenticated);
+
 #[derive(Error, Debug)]
 pub enum AuthError {
     #[error("something went wrong")]
     FailedToAuthorize,
     #[error("server config issue")]
     FailedToGetConfig,
+    #[error("bad user entry in secrets: {0}")]
+    InvalidSecrets(String),
+    #[error("user does not have the required role")]
+    MissingRole,
+    #[error("too many failed logins, locked out for {0} seconds")]
+    LockedOut(i64),
+    #[error("this route needs a username and password")]
+    PasswordRequired,
+}
+
+impl AuthError {
+    fn get_status(&self) -> http::Status {
+        match self {
+            AuthError::MissingRole => http::Status::Forbidden,
+            AuthError::LockedOut(_) => http::Status::TooManyRequests,
+            _ => http::Status::Unauthorized,
+        }
+    }
 }
 
 #[rocket::async_trait]
@@ -29,20 +76,223 @@ impl<'r> FromRequest<'r> for Authenticated {
     async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
         match Self::from_request_inner(req).await {
             Ok(a) => request::Outcome::Success(a),
-            Err(e) => request::Outcome::Error((http::Status::Unauthorized, e)),
+            Err(e) => request::Outcome::Error((e.get_status(), e)),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Uploader {
+    type Error = AuthError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        Authenticated::require_role(req, Role::Uploader)
+            .await
+            .map(Uploader)
+    }
+}
+
+impl Deref for Uploader {
+    type Target = Authenticated;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = AuthError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        Authenticated::require_role(req, Role::Admin)
+            .await
+            .map(Admin)
+    }
+}
+
+impl Deref for Admin {
+    type Target = Authenticated;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for PasswordAuthenticated {
+    type Error = AuthError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match Authenticated::from_password_inner(req).await {
+            Ok(a) => request::Outcome::Success(PasswordAuthenticated(a)),
+            Err(e) => request::Outcome::Error((e.get_status(), e)),
         }
     }
 }
 
+impl Deref for PasswordAuthenticated {
+    type Target = Authenticated;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 impl<'r> Authenticated {
     async fn from_request_inner(req: &'r Request<'_>) -> Result<Self, AuthError> {
         let authenticator = Self::get_authenticator(req)?;
+        let server_config = Self::get_server_config(req)?;
+        let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+        let throttle = AuthThrottle::new(server_config);
+        let ip = Self::get_client_ip(req);
+        if let Some(token) = Self::get_incoming_bearer_token(req) {
+            Self::check_lockout(&throttle, &operational_data, None, &ip)?;
+            return authenticator
+                .authenticate_token(&operational_data, token)
+                .inspect_err(|_| {
+                    throttle.note_failure(&operational_data, None, &ip, "bad bearer token")
+                });
+        }
+        if let Some(plex_token) = Self::get_incoming_plex_token(req, server_config) {
+            Self::check_lockout(&throttle, &operational_data, None, &ip)?;
+            return Self::authenticate_plex_token(req, authenticator, server_config, plex_token)
+                .await
+                .inspect_err(|_| {
+                    throttle.note_failure(&operational_data, None, &ip, "bad plex token")
+                });
+        }
         let user_auth = Self::get_incoming_basic_auth(req).await?;
-        match authenticator.is_authenticated(&user_auth) {
-            true => Ok(Authenticated {
+        Self::authenticate_password(authenticator, &throttle, &operational_data, &ip, user_auth)
+    }
+
+    /// a bearer or plex token is refused rather than ignored, so it can't be mistaken for a login.
+    async fn from_password_inner(req: &'r Request<'_>) -> Result<Self, AuthError> {
+        let authenticator = Self::get_authenticator(req)?;
+        let server_config = Self::get_server_config(req)?;
+        if Self::get_incoming_bearer_token(req).is_some()
+            || Self::get_incoming_plex_token(req, server_config).is_some()
+        {
+            return Err(AuthError::PasswordRequired);
+        }
+        let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+        let throttle = AuthThrottle::new(server_config);
+        let ip = Self::get_client_ip(req);
+        let user_auth = Self::get_incoming_basic_auth(req).await?;
+        Self::authenticate_password(authenticator, &throttle, &operational_data, &ip, user_auth)
+    }
+
+    fn authenticate_password(
+        authenticator: &Authenticator,
+        throttle: &AuthThrottle,
+        operational_data: &OperationalData,
+        ip: &str,
+        user_auth: BasicAuth,
+    ) -> Result<Self, AuthError> {
+        Self::check_lockout(throttle, operational_data, Some(&user_auth.username), ip)?;
+        match authenticator.authenticate(operational_data, &user_auth) {
+            Some(role) => Ok(Authenticated {
                 username: user_auth.username,
+                role,
             }),
-            false => Err(AuthError::FailedToAuthorize),
+            None => {
+                throttle.note_failure(
+                    operational_data,
+                    Some(&user_auth.username),
+                    ip,
+                    "bad username or password",
+                );
+                Err(AuthError::FailedToAuthorize)
+            }
+        }
+    }
+
+    /// plex users get the configured plex role, unless they collide with a user from Secrets.toml.
+    async fn authenticate_plex_token(
+        req: &'r Request<'_>,
+        authenticator: &Authenticator,
+        server_config: &ServerConfig,
+        plex_token: &str,
+    ) -> Result<Self, AuthError> {
+        let plex_authenticator = req
+            .rocket()
+            .state::<PlexAuthenticator>()
+            .ok_or(AuthError::FailedToGetConfig)?;
+        let username = plex_authenticator
+            .get_username(server_config, plex_token)
+            .await
+            .ok_or(AuthError::FailedToAuthorize)?;
+        // opened after the plex lookup since a connection can't be held across the await.
+        let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+        if authenticator.has_user(&operational_data, &username) {
+            println!("refusing plex login for {username}, the name belongs to a local user");
+            return Err(AuthError::FailedToAuthorize);
+        }
+        Ok(Authenticated {
+            username,
+            role: server_config.plex_auth_role,
+        })
+    }
+
+    fn check_lockout(
+        throttle: &AuthThrottle,
+        operational_data: &OperationalData,
+        username: Option<&str>,
+        ip: &str,
+    ) -> Result<(), AuthError> {
+        match throttle.get_lockout(operational_data, username, ip) {
+            Some(remaining_seconds) => {
+                println!(
+                    "refusing login from {ip} for {}, locked out for {remaining_seconds}s",
+                    username.unwrap_or("<no username>")
+                );
+                Err(AuthError::LockedOut(remaining_seconds))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// behind nginx this relies on rocket's `ip_header` (X-Real-IP by default) being set by the proxy.
+    fn get_client_ip(req: &'r Request<'_>) -> String {
+        req.client_ip()
+            .map(|ip| ip.to_string())
+            .unwrap_or("unknown".to_string())
+    }
+
+    async fn require_role(
+        req: &'r Request<'_>,
+        required_role: Role,
+    ) -> request::Outcome<Self, AuthError> {
+        match req.guard::<Authenticated>().await {
+            request::Outcome::Success(auth) if auth.role >= required_role => {
+                request::Outcome::Success(auth)
+            }
+            request::Outcome::Success(auth) => {
+                println!(
+                    "{} ({:?}) was denied a route requiring {:?}",
+                    auth.username, auth.role, required_role
+                );
+                let e = AuthError::MissingRole;
+                request::Outcome::Error((e.get_status(), e))
+            }
+            request::Outcome::Error(e) => request::Outcome::Error(e),
+            request::Outcome::Forward(status) => request::Outcome::Forward(status),
+        }
+    }
+
+    fn get_incoming_bearer_token(req: &'r Request<'_>) -> Option<&'r str> {
+        req.headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix(BEARER_PREFIX))
+    }
+
+    fn get_incoming_plex_token(
+        req: &'r Request<'_>,
+        server_config: &ServerConfig,
+    ) -> Option<&'r str> {
+        match server_config.plex_auth_enabled {
+            true => req.headers().get_one(PLEX_TOKEN_HEADER),
+            false => None,
         }
     }
 
@@ -59,31 +309,211 @@ impl<'r> Authenticated {
             _ => Err(AuthError::FailedToGetConfig),
         }
     }
+
+    fn get_server_config(req: &'r Request<'_>) -> Result<&'r ServerConfig, AuthError> {
+        req.rocket()
+            .state::<ServerConfig>()
+            .ok_or(AuthError::FailedToGetConfig)
+    }
 }
 
+pub const SECRETS_PATH: &str = "./Secrets.toml";
+
+#[derive(Clone)]
+struct UserEntry {
+    password_hash: String,
+    role: Role,
+    daily_quota_mb: Option<u32>,
+    lifetime_quota_mb: Option<u32>,
+}
+
+type UserTable = HashMap<String, UserEntry>;
+
+/// cloning shares the same user table, so a reload is seen by every clone.
+#[derive(Clone)]
 pub struct Authenticator {
-    users: HashMap<String, String>,
+    users: Arc<RwLock<UserTable>>,
+    /// verified against when the user does not exist so that lookups take the same time.
+    fake_password_hash: Arc<String>,
 }
 
 impl Authenticator {
     pub fn new() -> Result<Self, AuthError> {
-        let users = load_toml::<Users>("./Secrets.toml")
+        let users = Self::load_user_table()?;
+        let fake_password_hash =
+            hash_password("fake password").map_err(|e| AuthError::InvalidSecrets(e.to_string()))?;
+        Ok(Authenticator {
+            users: Arc::new(RwLock::new(users)),
+            fake_password_hash: Arc::new(fake_password_hash),
+        })
+    }
+
+    /// swaps in the users from Secrets.toml. if the file cannot be used the current users are kept,
+    /// so a bad edit does not lock everyone out.
+    pub fn reload(&self) -> Result<usize, AuthError> {
+        let users = Self::load_user_table()?;
+        let num_users = users.len();
+        *self.users.write().unwrap_or_else(PoisonError::into_inner) = users;
+        println!("reloaded {num_users} users from {SECRETS_PATH}");
+        Ok(num_users)
+    }
+
+    fn load_user_table() -> Result<UserTable, AuthError> {
+        let mut plaintext_users = Vec::new();
+        let users = try_load_toml::<Users>(SECRETS_PATH)
+            .map_err(AuthError::InvalidSecrets)?
             .users
             .into_iter()
-            .map(|user| (user.username, user.password))
-            .collect();
-        Ok(Authenticator { users })
+            .map(|user| {
+                if user.password.is_some() {
+                    plaintext_users.push(user.username.clone());
+                }
+                Self::build_user_entry(user)
+            })
+            .collect::<Result<UserTable, _>>()?;
+        if users.is_empty() {
+            return Err(AuthError::InvalidSecrets(
+                "there are no users defined".to_string(),
+            ));
+        }
+        if !plaintext_users.is_empty() {
+            println!(
+                "WARNING: Secrets.toml has plaintext passwords for: {}. \
+                replace each `password` with a `password_hash` generated by \
+                `cargo run --example hash_password`",
+                plaintext_users.join(", ")
+            );
+        }
+        Ok(users)
     }
 
-    fn is_authenticated(&self, auth: &BasicAuth) -> bool {
-        let fake_password = "fake password".to_string();
-        let testing_password = self.users.get(&auth.username).unwrap_or(&fake_password);
-        let user_was_found = self.users.contains_key(&auth.username);
-        let password_is_correct = Self::compare_str(testing_password, &auth.password);
-        user_was_found && password_is_correct
+    pub fn has_user(&self, operational_data: &OperationalData, username: &str) -> bool {
+        self.get_user(operational_data, username).is_some()
+    }
+
+    /// like has_user, but `Alice` counts as taken when `alice` exists.
+    pub fn is_username_taken(&self, operational_data: &OperationalData, username: &str) -> bool {
+        let lowercase = username.to_lowercase();
+        self.users
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .keys()
+            .any(|name| name.to_lowercase() == lowercase)
+            || operational_data.has_managed_user_ignoring_case(username)
+    }
+
+    pub fn is_secrets_user(&self, username: &str) -> bool {
+        self.users
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains_key(username)
+    }
+
+    /// the server wide quota, with any per user overrides from Secrets.toml applied.
+    pub fn get_quota(
+        &self,
+        server_config: &ServerConfig,
+        operational_data: &OperationalData,
+        username: &str,
+    ) -> Quota {
+        let user = self.get_user(operational_data, username);
+        let daily_quota_mb = user
+            .as_ref()
+            .and_then(|user| user.daily_quota_mb)
+            .or(server_config.daily_quota_mb);
+        let lifetime_quota_mb = user
+            .as_ref()
+            .and_then(|user| user.lifetime_quota_mb)
+            .or(server_config.lifetime_quota_mb);
+        Quota::from_mb(daily_quota_mb, lifetime_quota_mb)
+    }
+
+    /// Secrets.toml users take precedence over users that signed up with an invite.
+    fn get_user(&self, operational_data: &OperationalData, username: &str) -> Option<UserEntry> {
+        let secrets_user = self
+            .users
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(username)
+            .cloned();
+        secrets_user.or_else(|| {
+            let managed_user = operational_data.get_managed_user(username)?;
+            let role = managed_user
+                .role
+                .parse::<Role>()
+                .inspect_err(|e| println!("managed user {username} has a bad role: {e}"))
+                .ok()?;
+            Some(UserEntry {
+                password_hash: managed_user.password_hash,
+                role,
+                daily_quota_mb: None,
+                lifetime_quota_mb: None,
+            })
+        })
+    }
+
+    /// plaintext passwords are hashed in memory so that only hashes are ever compared against.
+    fn build_user_entry(user: User) -> Result<(String, UserEntry), AuthError> {
+        let username = user.username;
+        let role = user.role;
+        let daily_quota_mb = user.daily_quota_mb;
+        let lifetime_quota_mb = user.lifetime_quota_mb;
+        let password_hash = match (user.password_hash, user.password) {
+            (Some(password_hash), _) if is_valid_password_hash(&password_hash) => Ok(password_hash),
+            (Some(_), _) => Err(AuthError::InvalidSecrets(format!(
+                "{username} has a malformed password_hash"
+            ))),
+            (None, Some(password)) => {
+                hash_password(&password).map_err(|e| AuthError::InvalidSecrets(e.to_string()))
+            }
+            (None, None) => Err(AuthError::InvalidSecrets(format!(
+                "{username} has neither a password_hash nor a password"
+            ))),
+        }?;
+        Ok((
+            username,
+            UserEntry {
+                password_hash,
+                role,
+                daily_quota_mb,
+                lifetime_quota_mb,
+            },
+        ))
+    }
+
+    /// returns the user's role when the credentials are correct.
+    fn authenticate(&self, operational_data: &OperationalData, auth: &BasicAuth) -> Option<Role> {
+        let user = self.get_user(operational_data, &auth.username);
+        let testing_password_hash = user
+            .as_ref()
+            .map(|user| &user.password_hash)
+            .unwrap_or(&self.fake_password_hash);
+        let password_is_correct = verify_password(&auth.password, testing_password_hash);
+        match (user, password_is_correct) {
+            (Some(user), true) => Some(user.role),
+            _ => None,
+        }
     }
 
-    fn compare_str(a: &String, b: &String) -> bool {
-        a.trim() == b.trim()
+    /// tokens only identify the user, the role is still looked up so that role changes apply to existing tokens.
+    fn authenticate_token(
+        &self,
+        operational_data: &OperationalData,
+        token: &str,
+    ) -> Result<Authenticated, AuthError> {
+        let token_hash = hash_secret_token(token);
+        let api_token = operational_data
+            .get_api_token(&token_hash)
+            .filter(|api_token| api_token.is_usable(get_now_timestamp()))
+            .ok_or(AuthError::FailedToAuthorize)?;
+        let role = self
+            .get_user(operational_data, &api_token.username)
+            .map(|user| user.role)
+            .ok_or(AuthError::FailedToAuthorize)?;
+        operational_data.note_api_token_used(&token_hash);
+        Ok(Authenticated {
+            username: api_token.username,
+            role,
+        })
     }
 }
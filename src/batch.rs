@@ -0,0 +1,226 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    activities::upload::map_path_error,
+    authenticated::Authenticated,
+    config::{secrets_config::Role, server_config::ServerConfig},
+    data::operational_data::{
+        BatchItem, OperationalData, BATCH_COMPLETE, BATCH_FILE_EXPECTED, BATCH_FILE_SKIPPED,
+        BATCH_FILE_UPLOADED, BATCH_OPEN, BATCH_TIMED_OUT,
+    },
+    model::{BatchResponse, ListedBatchFile, MusicUploaderError},
+    path_template::SongFields,
+    path_utils::build_path,
+    scan::request_scan,
+    time_utils::get_now_timestamp,
+};
+
+const MAX_BATCH_FILES: usize = 1000;
+/// file names in the files header are separated by `/`, which a cleaned file name never has.
+const FILE_SEPARATOR: char = '/';
+
+/// declares the files an album upload will send. returns the batch id uploads are attached with.
+pub fn create_batch(
+    server_config: &ServerConfig,
+    username: &str,
+    artist: &String,
+    album: &String,
+    files: &str,
+) -> Result<String, MusicUploaderError> {
+    let mut seen = HashSet::new();
+    let file_names = files
+        .split(FILE_SEPARATOR)
+        .map(str::trim)
+        .filter(|file_name| !file_name.is_empty() && seen.insert(*file_name))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if file_names.is_empty() || file_names.len() > MAX_BATCH_FILES {
+        return Err(MusicUploaderError::ConstraintViolation(format!(
+            "a batch needs between 1 and {MAX_BATCH_FILES} files"
+        )));
+    }
+    for file_name in &file_names {
+        build_path(
+            server_config,
+            &SongFields::new(artist, album, file_name, None),
+        )
+        .map_err(map_path_error)?;
+    }
+    let id = format!("{:016x}", rand::random::<u64>());
+    let expires_at = get_now_timestamp() + server_config.batch_timeout_minutes as i64 * 60;
+    OperationalData::new(&server_config.server_operational_db_dir)
+        .create_batch(&id, username, artist, album, expires_at, &file_names)
+        .ok_or(MusicUploaderError::InternalServerError(
+            "Failed to create batch".to_string(),
+        ))?;
+    println!(
+        "{username} declared batch {id} with {} files for {artist} - {album}",
+        file_names.len()
+    );
+    Ok(id)
+}
+
+/// an upload can only be attached to an open batch of the same user that expects its file.
+pub fn check_batch_file(
+    server_config: &ServerConfig,
+    batch_id: &str,
+    username: &str,
+    file_name: &str,
+) -> Result<(), MusicUploaderError> {
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let batch = operational_data
+        .get_batch(batch_id)
+        .filter(|batch| batch.user == username)
+        .ok_or(MusicUploaderError::ConstraintViolation(format!(
+            "no batch {batch_id}"
+        )))?;
+    if batch.status != BATCH_OPEN {
+        return Err(MusicUploaderError::ConstraintViolation(format!(
+            "batch {batch_id} is {}",
+            batch.status
+        )));
+    }
+    let files = get_batch_files(&operational_data, batch_id)?;
+    match files.iter().any(|file| file.name == file_name) {
+        true => Ok(()),
+        false => Err(MusicUploaderError::ConstraintViolation(format!(
+            "{file_name} is not part of batch {batch_id}"
+        ))),
+    }
+}
+
+/// records that one of the batch's files made it, and queues a scan once none are left to come.
+/// song_dir is None for uploads still waiting in quarantine, which have nothing to scan yet.
+pub fn finish_batch_file(
+    server_config: &ServerConfig,
+    batch_id: &str,
+    file_name: &str,
+    song_dir: Option<&Path>,
+) {
+    record_batch_file(
+        server_config,
+        batch_id,
+        file_name,
+        BATCH_FILE_UPLOADED,
+        song_dir,
+    );
+}
+
+/// an upload of a song the library already has still counts towards its batch.
+pub fn skip_batch_file(
+    server_config: &ServerConfig,
+    batch_id: &str,
+    file_name: &str,
+    error: &MusicUploaderError,
+) {
+    if let MusicUploaderError::SongAlreadyExists | MusicUploaderError::DuplicateContent(_) = error {
+        record_batch_file(server_config, batch_id, file_name, BATCH_FILE_SKIPPED, None);
+    }
+}
+
+fn record_batch_file(
+    server_config: &ServerConfig,
+    batch_id: &str,
+    file_name: &str,
+    status: &str,
+    song_dir: Option<&Path>,
+) {
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let song_dir = song_dir
+        .map(|dir| dir.to_string_lossy().to_string())
+        .unwrap_or_default();
+    operational_data.finish_batch_file(batch_id, file_name, status, &song_dir);
+    let Some(files) = operational_data.get_batch_files(batch_id) else {
+        return;
+    };
+    if files.iter().any(|file| file.status == BATCH_FILE_EXPECTED) {
+        return;
+    }
+    println!("every file in batch {batch_id} is done");
+    if operational_data.close_batch(batch_id, BATCH_COMPLETE) == Some(true) {
+        scan_batch_dirs(server_config, batch_id, &operational_data);
+    }
+}
+
+/// closes the batches that ran out of time, queueing scans for whatever they did upload.
+pub fn close_expired_batches(server_config: &ServerConfig) {
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let Some(expired) = operational_data.get_expired_batches(get_now_timestamp()) else {
+        return;
+    };
+    for batch in expired {
+        if operational_data.close_batch(&batch.id, BATCH_TIMED_OUT) == Some(true) {
+            println!("batch {} timed out", batch.id);
+            scan_batch_dirs(server_config, &batch.id, &operational_data);
+        }
+    }
+}
+
+/// queues a scan of the directories the batch's songs were written to, if any were.
+fn scan_batch_dirs(
+    server_config: &ServerConfig,
+    batch_id: &str,
+    operational_data: &OperationalData,
+) {
+    let Some(files) = operational_data.get_batch_files(batch_id) else {
+        return;
+    };
+    let mut dirs = files
+        .into_iter()
+        .filter(|file| file.status == BATCH_FILE_UPLOADED && !file.song_dir.is_empty())
+        .map(|file| PathBuf::from(file.song_dir))
+        .collect::<Vec<_>>();
+    dirs.sort();
+    dirs.dedup();
+    if !dirs.is_empty() {
+        request_scan(server_config, &dirs, &format!("batch {batch_id}"));
+    }
+}
+
+/// the batch and how each of its files went, for its owner or an admin.
+pub fn get_batch_status(
+    server_config: &ServerConfig,
+    batch_id: &str,
+    auth: &Authenticated,
+) -> Result<BatchResponse, MusicUploaderError> {
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let batch = operational_data
+        .get_batch(batch_id)
+        .filter(|batch| batch.user == auth.username || auth.role >= Role::Admin)
+        .ok_or(MusicUploaderError::ConstraintViolation(format!(
+            "no batch {batch_id}"
+        )))?;
+    let files = get_batch_files(&operational_data, batch_id)?;
+    Ok(build_response(batch, files))
+}
+
+fn get_batch_files(
+    operational_data: &OperationalData,
+    batch_id: &str,
+) -> Result<Vec<ListedBatchFile>, MusicUploaderError> {
+    Ok(operational_data
+        .get_batch_files(batch_id)
+        .ok_or(MusicUploaderError::InternalServerError(format!(
+            "Failed to get the files of batch {batch_id}"
+        )))?
+        .into_iter()
+        .map(|file| ListedBatchFile {
+            name: file.file_name,
+            status: file.status,
+        })
+        .collect())
+}
+
+fn build_response(batch: BatchItem, files: Vec<ListedBatchFile>) -> BatchResponse {
+    BatchResponse {
+        id: batch.id,
+        artist: batch.artist,
+        album: batch.album,
+        status: batch.status,
+        expires: batch.expires_at,
+        files,
+    }
+}
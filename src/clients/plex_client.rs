@@ -48,6 +48,13 @@ impl PlexClient {
         self.send_with_server_token(request).await
     }
 
+    /// scans one directory of the library instead of all of it. the path is as plex sees it.
+    pub async fn trigger_partial_scan(&self, library_id: u16, path: &str) -> PlexClientResult<String> {
+        let url = self.build_local_url(&format!("library/sections/{library_id}/refresh"))?;
+        let request = self.http_client.get(url).query(&[("path", path)]);
+        self.send_with_server_token(request).await
+    }
+
     pub async fn get_resources(&self) -> PlexClientResult<GetResources> {
         let url = Self::build_external_url("resources")?;
         let request = self.http_client.get(url);
@@ -62,6 +69,19 @@ impl PlexClient {
         GetUserInfo::from_xml(&response)
     }
 
+    /// the client identifier of this plex media server, needed for the plex.tv server apis.
+    pub async fn get_server_identifier(&self) -> PlexClientResult<String> {
+        self.get_resources()
+            .await?
+            .devices
+            .into_iter()
+            .find(|item| item.product == "Plex Media Server")
+            .map(|item| item.client_identifier)
+            .ok_or(PlexClientError::MisunderstoodPlexResponse(
+                "Could not find the Plex Media Server device".to_string(),
+            ))
+    }
+
     async fn send_with_server_token(&self, request: RequestBuilder) -> PlexClientResult<String> {
         Self::send_with_token(request, &self.plex_token).await
     }
@@ -12,3 +12,12 @@ pub fn load_toml<T: DeserializeOwned>(path: &str) -> T {
         .expect("Failed reading the file");
     toml::from_str::<T>(&file_text).expect("failed parsing the toml")
 }
+
+/// same as load_toml but for files that can change while the server is running.
+pub fn try_load_toml<T: DeserializeOwned>(path: &str) -> Result<T, String> {
+    let mut file_text = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut file_text))
+        .map_err(|e| format!("failed to read {path}: {e}"))?;
+    toml::from_str::<T>(&file_text).map_err(|e| format!("failed parsing {path}: {e}"))
+}
@@ -1,3 +1,5 @@
+use std::str::FromStr;
+
 use rocket::serde::Deserialize;
 
 #[derive(Deserialize)]
@@ -10,5 +12,49 @@ pub struct Users {
 #[serde(crate = "rocket::serde")]
 pub struct User {
     pub username: String,
-    pub password: String,
+    /// PHC formatted hash, generate with `cargo run --example hash_password`
+    pub password_hash: Option<String>,
+    /// legacy plaintext password, still accepted so old Secrets.toml files keep working.
+    pub password: Option<String>,
+    #[serde(default)]
+    pub role: Role,
+    /// overrides the server wide daily_quota_mb for this user.
+    pub daily_quota_mb: Option<u32>,
+    /// overrides the server wide lifetime_quota_mb for this user.
+    pub lifetime_quota_mb: Option<u32>,
+}
+
+/// roles are ordered, each role can do everything the roles below it can.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum Role {
+    /// can search and look up playlists.
+    Reader,
+    /// can upload and trigger scans. users without a role were always able to upload so this is the default.
+    #[default]
+    Uploader,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Reader => "reader",
+            Role::Uploader => "uploader",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "reader" => Ok(Role::Reader),
+            "uploader" => Ok(Role::Uploader),
+            "admin" => Ok(Role::Admin),
+            other => Err(format!("unknown role: {other}")),
+        }
+    }
 }
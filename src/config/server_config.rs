@@ -1,5 +1,7 @@
 use rocket::serde;
 
+use crate::{config::secrets_config::Role, path_template::PathTemplate};
+
 #[derive(serde::Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub struct ServerConfig {
@@ -13,4 +15,115 @@ pub struct ServerConfig {
     pub plex_music_library_id: u16,
     pub server_operational_db_dir: String,
     pub temp_file_dir: String,
+    #[serde(default = "default_api_token_lifetime_days")]
+    pub api_token_lifetime_days: u32,
+    #[serde(default = "default_auth_failure_threshold")]
+    pub auth_failure_threshold: u32,
+    #[serde(default = "default_auth_failure_window_minutes")]
+    pub auth_failure_window_minutes: u32,
+    #[serde(default = "default_auth_lockout_base_seconds")]
+    pub auth_lockout_base_seconds: u32,
+    /// lets users the plex server is shared with sign in using their plex access token.
+    #[serde(default)]
+    pub plex_auth_enabled: bool,
+    #[serde(default)]
+    pub plex_auth_role: Role,
+    /// megabytes each user may upload per utc day, unlimited when not set.
+    #[serde(default)]
+    pub daily_quota_mb: Option<u32>,
+    /// megabytes each user may upload in total, unlimited when not set.
+    #[serde(default)]
+    pub lifetime_quota_mb: Option<u32>,
+    #[serde(default)]
+    pub tag_policy: TagPolicy,
+    #[serde(default)]
+    pub path_template: PathTemplate,
+    #[serde(default)]
+    pub sanitize_mode: SanitizeMode,
+    /// where songs go when an upload replaces them, replacing is disabled when not set.
+    #[serde(default)]
+    pub trash_dir: Option<String>,
+    #[serde(default = "default_cover_art_max_mb")]
+    pub cover_art_max_mb: u32,
+    /// the largest width or height in pixels an uploaded cover may have.
+    #[serde(default = "default_cover_art_max_dimension")]
+    pub cover_art_max_dimension: u32,
+    #[serde(default = "default_max_archive_mb")]
+    pub max_archive_mb: u32,
+    /// users whose uploads wait in quarantine_dir until an admin approves them.
+    #[serde(default)]
+    pub moderated_users: Vec<String>,
+    #[serde(default)]
+    pub quarantine_dir: Option<String>,
+    /// how long a batch waits for its files before scanning with whatever made it.
+    #[serde(default = "default_batch_timeout_minutes")]
+    pub batch_timeout_minutes: u32,
+    /// scan requests are collected for this long before plex is asked to scan.
+    #[serde(default = "default_scan_debounce_seconds")]
+    pub scan_debounce_seconds: u32,
+    /// upload_dir as plex sees it, when plex runs somewhere the library is mounted elsewhere.
+    #[serde(default)]
+    pub plex_upload_dir: Option<String>,
+}
+
+/// what to do when the artist/album headers disagree with the tags embedded in the song.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum TagPolicy {
+    /// keep the headers and log the mismatch.
+    #[default]
+    Warn,
+    /// file the song under its tags, falling back to the headers for missing tags.
+    PreferTags,
+    /// refuse the upload.
+    Reject,
+}
+
+/// how artist, album and file names are made safe to use as paths.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum SanitizeMode {
+    /// replace anything that isn't plain ascii letters, digits or a few symbols.
+    #[default]
+    Legacy,
+    /// normalize, then spell anything that isn't ascii with ascii letters.
+    Transliterate,
+    /// normalize, keeping letters and digits from any script.
+    Unicode,
+}
+
+fn default_api_token_lifetime_days() -> u32 {
+    30
+}
+
+fn default_auth_failure_threshold() -> u32 {
+    5
+}
+
+fn default_auth_failure_window_minutes() -> u32 {
+    15
+}
+
+fn default_auth_lockout_base_seconds() -> u32 {
+    30
+}
+
+fn default_cover_art_max_mb() -> u32 {
+    10
+}
+
+fn default_cover_art_max_dimension() -> u32 {
+    5000
+}
+
+fn default_max_archive_mb() -> u32 {
+    1000
+}
+
+fn default_batch_timeout_minutes() -> u32 {
+    60
+}
+
+fn default_scan_debounce_seconds() -> u32 {
+    30
 }
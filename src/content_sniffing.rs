@@ -0,0 +1,425 @@
+use std::{
+    ffi::OsStr,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::model::MusicUploaderError;
+
+/// how much of the start of a file is needed to recognize its container.
+const SNIFF_LENGTH: usize = 64 * 1024;
+
+#[derive(Debug, PartialEq)]
+enum AudioContainer {
+    Mp3,
+    Wave,
+    Mp4,
+    Adts,
+    Flac,
+    Ogg,
+}
+
+impl AudioContainer {
+    fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "mp3" => Some(Self::Mp3),
+            "wav" | "wave" => Some(Self::Wave),
+            "m4a" | "mp4" | "alac" => Some(Self::Mp4),
+            "aac" => Some(Self::Adts),
+            "flac" => Some(Self::Flac),
+            "ogg" | "oga" | "opus" => Some(Self::Ogg),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Mp3 => "MP3",
+            Self::Wave => "WAVE",
+            Self::Mp4 => "MP4",
+            Self::Adts => "AAC",
+            Self::Flac => "FLAC",
+            Self::Ogg => "OGG",
+        }
+    }
+
+    fn matches<R: Read + Seek>(&self, start: &[u8], file: &mut R) -> bool {
+        match self {
+            Self::Mp3 => is_after_id3_tag(start, file, MPEG_HEADER_LENGTH, is_mpeg_frame_header),
+            Self::Wave => is_wave(start),
+            Self::Mp4 => is_mp4(start),
+            Self::Adts => is_after_id3_tag(start, file, ADTS_HEADER_LENGTH, is_adts_header),
+            Self::Flac => is_after_id3_tag(start, file, FLAC_HEADER_LENGTH, is_flac),
+            Self::Ogg => is_ogg(start),
+        }
+    }
+}
+
+/// checks the start of a file against the container its extension promises.
+/// extensions we don't know the layout of are let through, valid_extensions already allowed them.
+pub fn validate_file_content<R: Read + Seek>(
+    file_path: &Path,
+    file: &mut R,
+) -> Result<(), MusicUploaderError> {
+    let extension = file_path
+        .extension()
+        .and_then(OsStr::to_str)
+        .unwrap_or_default();
+    let Some(container) = AudioContainer::from_extension(extension) else {
+        println!("no content check for .{extension} files, accepting as is");
+        return Ok(());
+    };
+    let mut start = Vec::with_capacity(SNIFF_LENGTH);
+    file.seek(SeekFrom::Start(0))
+        .and_then(|_| file.take(SNIFF_LENGTH as u64).read_to_end(&mut start))
+        .map_err(|e| {
+            MusicUploaderError::InternalServerError(format!("Failed to read temp file: {e}"))
+        })?;
+    match container.matches(&start, file) {
+        true => Ok(()),
+        false => Err(MusicUploaderError::ConstraintViolation(format!(
+            "file contents are not a valid {} file, which the .{extension} extension requires",
+            container.name()
+        ))),
+    }
+}
+
+const MPEG_HEADER_LENGTH: usize = 4;
+const ADTS_HEADER_LENGTH: usize = 7;
+const FLAC_HEADER_LENGTH: usize = 8;
+
+/// mp3 and raw aac streams can start with an ID3v2 tag, the first frame header follows it.
+/// some taggers put one in front of flac files too, even though flac has its own tags.
+fn is_after_id3_tag<R: Read + Seek>(
+    start: &[u8],
+    file: &mut R,
+    header_length: usize,
+    is_header: fn(&[u8]) -> bool,
+) -> bool {
+    match get_id3_tag_length(start) {
+        // the first audio frame can be past what we read if the tag holds cover art.
+        Some(tag_length) if tag_length + header_length > start.len() => {
+            let mut header = vec![0u8; header_length];
+            file.seek(SeekFrom::Start(tag_length as u64))
+                .and_then(|_| file.read_exact(&mut header))
+                .is_ok_and(|_| is_header(&header))
+        }
+        Some(tag_length) => is_header(&start[tag_length..]),
+        None => is_header(start),
+    }
+}
+
+/// length of a leading ID3v2 tag including its header and footer.
+pub fn get_id3_tag_length(start: &[u8]) -> Option<usize> {
+    if start.len() < 10 || &start[0..3] != b"ID3" || start[3] == 0xFF || start[4] == 0xFF {
+        return None;
+    }
+    let size = read_syncsafe_u32(&start[6..10])?;
+    let has_footer = start[5] & 0x10 != 0;
+    Some(10 + size as usize + if has_footer { 10 } else { 0 })
+}
+
+/// ID3v2 sizes use 7 bits per byte so they can never look like a frame sync.
+pub fn read_syncsafe_u32(bytes: &[u8]) -> Option<u32> {
+    if bytes.len() < 4 || bytes[..4].iter().any(|byte| byte & 0x80 != 0) {
+        return None;
+    }
+    Some(
+        bytes[..4]
+            .iter()
+            .fold(0, |size, byte| (size << 7) | *byte as u32),
+    )
+}
+
+fn is_mpeg_frame_header(bytes: &[u8]) -> bool {
+    if bytes.len() < MPEG_HEADER_LENGTH {
+        return false;
+    }
+    let sync = bytes[0] == 0xFF && bytes[1] & 0xE0 == 0xE0;
+    let version = (bytes[1] >> 3) & 0b11;
+    let layer = (bytes[1] >> 1) & 0b11;
+    let bitrate = bytes[2] >> 4;
+    let sample_rate = (bytes[2] >> 2) & 0b11;
+    // 01 is a reserved version, 00 a reserved layer, the rest are "bad" values per the spec.
+    sync && version != 0b01 && layer != 0b00 && bitrate != 0b1111 && sample_rate != 0b11
+}
+
+fn is_adts_header(bytes: &[u8]) -> bool {
+    if bytes.len() < ADTS_HEADER_LENGTH {
+        return false;
+    }
+    // 12 sync bits, then the layer which is always 00.
+    let sync = bytes[0] == 0xFF && bytes[1] & 0xF6 == 0xF0;
+    let sample_rate = (bytes[2] >> 2) & 0b1111;
+    let frame_length =
+        ((bytes[3] as usize & 0b11) << 11) | ((bytes[4] as usize) << 3) | (bytes[5] as usize >> 5);
+    // sample rate indexes past 12 are reserved, a frame holds at least its own header.
+    sync && sample_rate <= 12 && frame_length >= ADTS_HEADER_LENGTH
+}
+
+fn is_wave(start: &[u8]) -> bool {
+    if start.len() < 12 || &start[0..4] != b"RIFF" || &start[8..12] != b"WAVE" {
+        return false;
+    }
+    // the fmt chunk is usually first but other chunks are allowed ahead of it.
+    let mut offset = 12;
+    while offset + 8 <= start.len() {
+        let chunk_id = &start[offset..offset + 4];
+        let chunk_size = read_u32_le(&start[offset + 4..offset + 8]) as usize;
+        let body = offset + 8;
+        if chunk_id == b"fmt " {
+            return chunk_size >= 16
+                && start.len() >= body + 8
+                && read_u16_le(&start[body..]) != 0
+                && read_u16_le(&start[body + 2..]) != 0
+                && read_u32_le(&start[body + 4..]) != 0;
+        }
+        // chunks are padded to an even length.
+        offset = body + chunk_size + chunk_size % 2;
+    }
+    false
+}
+
+fn is_mp4(start: &[u8]) -> bool {
+    if start.len() < 12 || &start[4..8] != b"ftyp" {
+        return false;
+    }
+    let box_size = u32::from_be_bytes([start[0], start[1], start[2], start[3]]);
+    let major_brand = &start[8..12];
+    box_size >= 16
+        && box_size.is_multiple_of(4)
+        && major_brand
+            .iter()
+            .all(|byte| byte.is_ascii_alphanumeric() || *byte == b' ')
+}
+
+fn is_flac(start: &[u8]) -> bool {
+    // STREAMINFO has to be the first metadata block and is always 34 bytes.
+    start.len() >= FLAC_HEADER_LENGTH
+        && &start[0..4] == b"fLaC"
+        && start[4] & 0x7F == 0
+        && start[5..8] == [0, 0, 34]
+}
+
+fn is_ogg(start: &[u8]) -> bool {
+    // capture pattern, stream structure version 0, and the first page begins a stream.
+    start.len() >= 27 && &start[0..4] == b"OggS" && start[4] == 0 && start[5] & 0x02 != 0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ImageInfo {
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// recognizes jpeg and png images and reads their dimensions from the header.
+pub fn get_image_info(bytes: &[u8]) -> Option<ImageInfo> {
+    get_png_info(bytes).or_else(|| get_jpeg_info(bytes))
+}
+
+fn get_png_info(bytes: &[u8]) -> Option<ImageInfo> {
+    // IHDR is always the first chunk, right after the signature.
+    if bytes.len() < 24 || &bytes[0..8] != b"\x89PNG\r\n\x1a\n" || &bytes[12..16] != b"IHDR" {
+        return None;
+    }
+    Some(ImageInfo {
+        format: ImageFormat::Png,
+        width: read_u32_be(&bytes[16..20]),
+        height: read_u32_be(&bytes[20..24]),
+    })
+}
+
+fn get_jpeg_info(bytes: &[u8]) -> Option<ImageInfo> {
+    if bytes.len() < 4 || bytes[0..2] != [0xFF, 0xD8] {
+        return None;
+    }
+    // walk the segments until the frame header, which holds the dimensions.
+    let mut offset = 2;
+    while offset + 4 <= bytes.len() {
+        if bytes[offset] != 0xFF {
+            return None;
+        }
+        let marker = bytes[offset + 1];
+        match marker {
+            // padding before a marker.
+            0xFF => {
+                offset += 1;
+                continue;
+            }
+            // markers without a length.
+            0x01 | 0xD0..=0xD7 => {
+                offset += 2;
+                continue;
+            }
+            // the image data started without a frame header.
+            0xD9 | 0xDA => return None,
+            _ => {}
+        }
+        let length = read_u16_be(&bytes[offset + 2..]) as usize;
+        let is_frame_header =
+            matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+        if is_frame_header {
+            if length < 7 || offset + 9 > bytes.len() {
+                return None;
+            }
+            return Some(ImageInfo {
+                format: ImageFormat::Jpeg,
+                height: read_u16_be(&bytes[offset + 5..]) as u32,
+                width: read_u16_be(&bytes[offset + 7..]) as u32,
+            });
+        }
+        offset += 2 + length;
+    }
+    None
+}
+
+fn read_u16_be(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+fn read_u32_be(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn read_u16_le(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    const MPEG_FRAME_HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x64];
+    // mpeg-4 aac lc, 44100hz, stereo, a 371 byte frame.
+    const ADTS_FRAME_HEADER: [u8; 7] = [0xFF, 0xF1, 0x50, 0x80, 0x2E, 0x7F, 0xFC];
+
+    fn check(file_name: &str, contents: &[u8]) -> bool {
+        validate_file_content(Path::new(file_name), &mut Cursor::new(contents)).is_ok()
+    }
+
+    fn build_wave() -> Vec<u8> {
+        let mut wave = b"RIFF\x24\x00\x00\x00WAVEfmt \x10\x00\x00\x00".to_vec();
+        // pcm, 2 channels, 44100hz
+        wave.extend_from_slice(&[1, 0, 2, 0, 0x44, 0xAC, 0, 0]);
+        wave.extend_from_slice(&[0x10, 0xB1, 2, 0, 4, 0, 16, 0]);
+        wave
+    }
+
+    #[test]
+    fn test_mp3_with_and_without_id3() {
+        assert!(check("song.mp3", &MPEG_FRAME_HEADER));
+        let mut tagged = b"ID3\x04\x00\x00\x00\x00\x00\x02ab".to_vec();
+        tagged.extend_from_slice(&MPEG_FRAME_HEADER);
+        assert!(check("song.mp3", &tagged));
+        // an id3 tag followed by garbage is a broken download.
+        let mut broken = b"ID3\x04\x00\x00\x00\x00\x00\x02ab".to_vec();
+        broken.extend_from_slice(b"<html>");
+        assert!(!check("song.mp3", &broken));
+    }
+
+    #[test]
+    fn test_mp3_with_id3_tag_past_sniff_length() {
+        // a 70000 byte tag, as big cover art would make it.
+        let mut tagged = b"ID3\x04\x00\x00\x00\x04\x22\x70".to_vec();
+        tagged.resize(10 + 70000, 0);
+        assert!(SNIFF_LENGTH < tagged.len());
+        let mut broken = tagged.clone();
+        tagged.extend_from_slice(&MPEG_FRAME_HEADER);
+        assert!(check("song.mp3", &tagged));
+        broken.extend_from_slice(b"<html>");
+        assert!(!check("song.mp3", &broken));
+        // the tag claims more than the file holds.
+        broken.truncate(1000);
+        assert!(!check("song.mp3", &broken));
+    }
+
+    #[test]
+    fn test_text_is_not_audio() {
+        let text = b"this is definitely a song, trust me. it is long enough to read.";
+        for file_name in ["a.mp3", "a.wav", "a.m4a", "a.aac", "a.flac", "a.ogg"] {
+            assert!(!check(file_name, text), "{file_name}");
+        }
+    }
+
+    #[test]
+    fn test_containers_need_matching_extension() {
+        let wave = build_wave();
+        assert!(check("song.wav", &wave));
+        assert!(check("song.WAVE", &wave));
+        assert!(!check("song.mp3", &wave));
+        assert!(!check("song.flac", &MPEG_FRAME_HEADER));
+    }
+
+    #[test]
+    fn test_other_containers() {
+        let mp4 = b"\x00\x00\x00\x20ftypM4A \x00\x00\x02\x00M4A mp42isom";
+        assert!(check("song.m4a", mp4));
+        assert!(!check("song.aac", mp4));
+        assert!(check("song.aac", &ADTS_FRAME_HEADER));
+        let mut tagged = b"ID3\x04\x00\x00\x00\x00\x00\x02ab".to_vec();
+        tagged.extend_from_slice(&ADTS_FRAME_HEADER);
+        assert!(check("song.aac", &tagged));
+        // an mp3 frame has a non zero layer.
+        assert!(!check("song.aac", &MPEG_FRAME_HEADER));
+        let flac = b"fLaC\x00\x00\x00\x22\x10\x00\x10\x00";
+        assert!(check("song.flac", flac));
+        let mut tagged = b"ID3\x03\x00\x00\x00\x00\x00\x02ab".to_vec();
+        tagged.extend_from_slice(flac);
+        assert!(check("song.flac", &tagged));
+        let mut broken = b"ID3\x03\x00\x00\x00\x00\x00\x02ab".to_vec();
+        broken.extend_from_slice(b"<html>, not flac");
+        assert!(!check("song.flac", &broken));
+        let mut ogg = b"OggS\x00\x02".to_vec();
+        ogg.resize(27, 0);
+        assert!(check("song.ogg", &ogg));
+    }
+
+    #[test]
+    fn test_unknown_extensions_are_not_checked() {
+        assert!(check("song.wma", b"anything"));
+    }
+
+    #[test]
+    fn test_image_dimensions() {
+        let mut png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+        png.extend_from_slice(&[0, 0, 2, 0, 0, 0, 1, 0x2C, 8, 2, 0, 0, 0]);
+        let expected = ImageInfo {
+            format: ImageFormat::Png,
+            width: 512,
+            height: 300,
+        };
+        assert_eq!(Some(expected), get_image_info(&png));
+        // an APP0 segment comes before the baseline frame header.
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0, 4, b'J', b'F'];
+        jpeg.extend_from_slice(&[0xFF, 0xC0, 0, 11, 8, 0x01, 0xF4, 0x03, 0x20, 3]);
+        let expected = ImageInfo {
+            format: ImageFormat::Jpeg,
+            width: 800,
+            height: 500,
+        };
+        assert_eq!(Some(expected), get_image_info(&jpeg));
+        assert_eq!(None, get_image_info(&[0xFF, 0xD8, 0xFF, 0xDA, 0, 2]));
+        assert_eq!(None, get_image_info(b"GIF89a, not supported"));
+    }
+}
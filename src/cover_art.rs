@@ -0,0 +1,289 @@
+use std::{
+    ffi::OsStr,
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::server_config::ServerConfig,
+    content_sniffing::get_image_info,
+    data::operational_data::OperationalData,
+    data_validation::write_new_file_atomically,
+    model::MusicUploaderError,
+    path_utils::get_library_path,
+    tags::{
+        get_mp4_item_data, mp4_children, read_id3_body, read_mp4_ilst, visit_flac_blocks, Id3Frames,
+    },
+};
+
+/// the file names plex looks for album art under, cover.* is what we write.
+const COVER_ART_NAMES: [&str; 6] = [
+    "cover.jpg",
+    "cover.jpeg",
+    "cover.png",
+    "folder.jpg",
+    "folder.jpeg",
+    "folder.png",
+];
+/// picture types shared by ID3 APIC frames and FLAC PICTURE blocks.
+const PICTURE_TYPE_OTHER: u32 = 0;
+const PICTURE_TYPE_FRONT_COVER: u32 = 3;
+
+/// cover art files already next to an album's songs.
+pub fn find_cover_art(album_dir: &Path) -> Vec<PathBuf> {
+    COVER_ART_NAMES
+        .iter()
+        .map(|name| album_dir.join(name))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+/// writes the song's embedded front cover next to it, unless the album already has cover art.
+/// this never fails the upload, what happened is recorded in the operational db instead.
+pub fn extract_cover_art(server_config: &ServerConfig, song_path: &Path) {
+    let result = extract_cover_art_inner(song_path);
+    let song = get_library_path(server_config, song_path);
+    let (description, cover_path) = match &result {
+        Ok(Some(cover_path)) if cover_path.extension() == Some(OsStr::new("png")) => (
+            "extracted png art as cover.png instead of cover.jpg",
+            get_library_path(server_config, cover_path),
+        ),
+        Ok(Some(cover_path)) => ("extracted", get_library_path(server_config, cover_path)),
+        Ok(None) => ("no embedded front cover", String::new()),
+        Err(CoverArtError::AlreadyHasCover(cover_path)) => (
+            "album already has cover art",
+            get_library_path(server_config, cover_path),
+        ),
+        Err(CoverArtError::UnsupportedImage) => {
+            ("embedded art is not a jpeg or png", String::new())
+        }
+        Err(CoverArtError::Failed(e)) => {
+            println!("failed to extract cover art from {song}: {e}");
+            ("failed", String::new())
+        }
+    };
+    println!("cover art for {song}: {description} {cover_path}");
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    operational_data.note_cover_art_extraction(&song, description, &cover_path);
+}
+
+enum CoverArtError {
+    AlreadyHasCover(PathBuf),
+    UnsupportedImage,
+    Failed(String),
+}
+
+fn extract_cover_art_inner(song_path: &Path) -> Result<Option<PathBuf>, CoverArtError> {
+    let album_dir = song_path
+        .parent()
+        .ok_or(CoverArtError::Failed("song has no directory".to_string()))?;
+    if let Some(existing_cover) = find_cover_art(album_dir).into_iter().next() {
+        return Err(CoverArtError::AlreadyHasCover(existing_cover));
+    }
+    let file = File::open(song_path).map_err(|e| CoverArtError::Failed(e.to_string()))?;
+    let Some(image) = read_front_cover(&mut BufReader::new(file)) else {
+        return Ok(None);
+    };
+    let image_info = get_image_info(&image).ok_or(CoverArtError::UnsupportedImage)?;
+    // png art is written as cover.png as is, re-encoding it as cover.jpg would only lose quality.
+    let cover_path = album_dir.join(format!("cover.{}", image_info.format.extension()));
+    // another song from the album may have just written its cover.
+    write_new_file_atomically(&cover_path, |file| file.write_all(&image)).map_err(
+        |e: MusicUploaderError| match cover_path.exists() {
+            true => CoverArtError::AlreadyHasCover(cover_path.clone()),
+            false => CoverArtError::Failed(e.to_string()),
+        },
+    )?;
+    Ok(Some(cover_path))
+}
+
+/// the embedded front cover, or failing that the first picture with no particular type.
+/// reads ID3v2 APIC frames, FLAC PICTURE blocks and the MP4 covr atom.
+pub fn read_front_cover<R: Read + Seek>(reader: &mut R) -> Option<Vec<u8>> {
+    let mut magic = [0u8; 8];
+    reader.seek(SeekFrom::Start(0)).ok()?;
+    reader.read_exact(&mut magic).ok()?;
+    match &magic {
+        m if &m[0..3] == b"ID3" => read_id3_cover(reader),
+        m if &m[0..4] == b"fLaC" => read_flac_cover(reader),
+        m if &m[4..8] == b"ftyp" => read_mp4_cover(reader),
+        _ => None,
+    }
+}
+
+fn pick_front_cover(mut pictures: Vec<(u32, Vec<u8>)>) -> Option<Vec<u8>> {
+    let find = |picture_type| pictures.iter().position(|(t, _)| *t == picture_type);
+    let index = find(PICTURE_TYPE_FRONT_COVER).or_else(|| find(PICTURE_TYPE_OTHER))?;
+    Some(pictures.swap_remove(index).1)
+}
+
+fn read_id3_cover<R: Read + Seek>(reader: &mut R) -> Option<Vec<u8>> {
+    let (body, offset, version, flags) = read_id3_body(reader)?;
+    let pictures = Id3Frames::new(&body, offset, version, flags)
+        .filter(|frame| frame.id == b"APIC" || frame.id == b"PIC")
+        .filter_map(|frame| parse_id3_picture(&frame.data, version))
+        .collect();
+    pick_front_cover(pictures)
+}
+
+/// APIC is encoding, mime type, picture type, description then the image.
+/// ID3v2.2's PIC has a three letter image format in place of the mime type.
+fn parse_id3_picture(data: &[u8], version: u8) -> Option<(u32, Vec<u8>)> {
+    let encoding = *data.first()?;
+    let type_offset = match version {
+        2 => 4,
+        _ => 1 + data.get(1..)?.iter().position(|byte| *byte == 0)? + 1,
+    };
+    let picture_type = *data.get(type_offset)?;
+    let description = data.get(type_offset + 1..)?;
+    // utf-16 descriptions end with two zero bytes.
+    let description_length = match encoding {
+        1 | 2 => 2 * description.chunks_exact(2).position(|c| c == [0, 0])? + 2,
+        _ => description.iter().position(|byte| *byte == 0)? + 1,
+    };
+    Some((
+        picture_type as u32,
+        description.get(description_length..)?.to_vec(),
+    ))
+}
+
+fn read_flac_cover<R: Read + Seek>(reader: &mut R) -> Option<Vec<u8>> {
+    let mut pictures = Vec::new();
+    // a broken block later on shouldn't lose the pictures before it.
+    let _ = visit_flac_blocks(reader, |block_type, block| {
+        if block_type == 6 {
+            pictures.extend(parse_flac_picture(block));
+        }
+        true
+    });
+    pick_front_cover(pictures)
+}
+
+/// a PICTURE block is the type, mime type, description, four numbers describing the image and
+/// then the image, with every variable length part prefixed by its length.
+fn parse_flac_picture(block: &[u8]) -> Option<(u32, Vec<u8>)> {
+    let read_u32 = |offset: usize| -> Option<u32> {
+        let bytes = block.get(offset..offset + 4)?;
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+    let picture_type = read_u32(0)?;
+    let description_offset = 8 + read_u32(4)? as usize;
+    // width, height, color depth and number of colors.
+    let data_length_offset = description_offset + 4 + read_u32(description_offset)? as usize + 16;
+    let data_offset = data_length_offset + 4;
+    let data_length = read_u32(data_length_offset)? as usize;
+    Some((
+        picture_type,
+        block.get(data_offset..data_offset + data_length)?.to_vec(),
+    ))
+}
+
+fn read_mp4_cover<R: Read + Seek>(reader: &mut R) -> Option<Vec<u8>> {
+    let ilst = read_mp4_ilst(reader)?;
+    let (_, item) = mp4_children(&ilst).find(|(name, _)| *name == b"covr")?;
+    get_mp4_item_data(item).map(|(_, image)| image.to_vec())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const JPEG: &[u8] = b"\xFF\xD8\xFF\xC0\x00\x0B\x08\x01\xF4\x03\x20\x03\xFF\xD9";
+
+    fn build_apic(picture_type: u8, image: &[u8]) -> Vec<u8> {
+        let data = [
+            b"\x00image/jpeg\x00",
+            &[picture_type][..],
+            b"cover\x00",
+            image,
+        ]
+        .concat();
+        let mut frame = b"APIC".to_vec();
+        frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(&data);
+        frame
+    }
+
+    fn build_id3(frames: &[Vec<u8>]) -> Vec<u8> {
+        let body = frames.concat();
+        let size = body.len() as u32;
+        let mut tag = b"ID3\x03\x00\x00".to_vec();
+        tag.extend(
+            (0..4)
+                .rev()
+                .map(|shift| ((size >> (shift * 7)) & 0x7F) as u8),
+        );
+        tag.extend_from_slice(&body);
+        tag.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x64]);
+        tag
+    }
+
+    fn build_flac_picture(picture_type: u32, image: &[u8]) -> Vec<u8> {
+        let mut block = picture_type.to_be_bytes().to_vec();
+        for part in [&b"image/jpeg"[..], b""] {
+            block.extend_from_slice(&(part.len() as u32).to_be_bytes());
+            block.extend_from_slice(part);
+        }
+        block.extend_from_slice(&[0; 16]);
+        block.extend_from_slice(&(image.len() as u32).to_be_bytes());
+        block.extend_from_slice(image);
+        block
+    }
+
+    #[test]
+    fn test_id3_front_cover_is_preferred() {
+        let song = build_id3(&[build_apic(4, b"back cover"), build_apic(3, JPEG)]);
+        assert_eq!(
+            Some(JPEG.to_vec()),
+            read_front_cover(&mut Cursor::new(song))
+        );
+        let song = build_id3(&[build_apic(0, JPEG)]);
+        assert_eq!(
+            Some(JPEG.to_vec()),
+            read_front_cover(&mut Cursor::new(song))
+        );
+        let song = build_id3(&[build_apic(4, b"back cover")]);
+        assert_eq!(None, read_front_cover(&mut Cursor::new(song)));
+    }
+
+    #[test]
+    fn test_flac_picture() {
+        let mut song = b"fLaC".to_vec();
+        let streaminfo = [0u8; 34];
+        song.extend_from_slice(&[0, 0, 0, 34]);
+        song.extend_from_slice(&streaminfo);
+        let picture = build_flac_picture(3, JPEG);
+        song.push(0x80 | 6);
+        song.extend_from_slice(&(picture.len() as u32).to_be_bytes()[1..]);
+        song.extend_from_slice(&picture);
+        assert_eq!(
+            Some(JPEG.to_vec()),
+            read_front_cover(&mut Cursor::new(song))
+        );
+    }
+
+    #[test]
+    fn test_mp4_covr() {
+        let data = [&13u32.to_be_bytes()[..], &[0, 0, 0, 0], JPEG].concat();
+        let build_box = |name: &[u8], contents: &[u8]| {
+            [
+                &((contents.len() + 8) as u32).to_be_bytes()[..],
+                name,
+                contents,
+            ]
+            .concat()
+        };
+        let ilst = build_box(b"ilst", &build_box(b"covr", &build_box(b"data", &data)));
+        let meta = build_box(b"meta", &[&[0, 0, 0, 0][..], &ilst].concat());
+        let moov = build_box(b"moov", &build_box(b"udta", &meta));
+        let song = [build_box(b"ftyp", b"M4A \x00\x00\x02\x00"), moov].concat();
+        assert_eq!(
+            Some(JPEG.to_vec()),
+            read_front_cover(&mut Cursor::new(song))
+        );
+    }
+}
@@ -1,6 +1,8 @@
+use std::collections::HashSet;
+
 use rusqlite::{params, Connection};
 
-use crate::time_utils::get_now_timestamp;
+use crate::{data::add_column_if_missing, time_utils::get_now_timestamp};
 
 pub struct Metrics {
     conn: Connection,
@@ -27,6 +29,29 @@ impl Metrics {
                 [],
             )
             .expect("could not create table :(");
+        add_column_if_missing(
+            metrics.get_conn(),
+            "songUploads",
+            "bytes",
+            "INTEGER not null default 0",
+        )
+        .expect("could not add bytes to songUploads");
+        add_column_if_missing(
+            metrics.get_conn(),
+            "songUploads",
+            "hash",
+            "TEXT not null default ''",
+        )
+        .expect("could not add hash to songUploads");
+        add_column_if_missing(metrics.get_conn(), "songUploads", "replacedBy", "TEXT")
+            .expect("could not add replacedBy to songUploads");
+        metrics
+            .get_conn()
+            .execute(
+                "create index if not exists songUploadsHash on songUploads (hash)",
+                [],
+            )
+            .expect("could not create index :(");
         metrics
     }
 
@@ -34,12 +59,12 @@ impl Metrics {
         &self.conn
     }
 
-    pub fn note_upload(&self, song_path: &String, user: &String) -> bool {
+    pub fn note_upload(&self, song_path: &String, user: &String, bytes: u64, hash: &str) -> bool {
         match self.get_conn().execute(
             "insert into songUploads \
-            (user, path, timestamp) \
-            values (?1, ?2, ?3)",
-            params![user, song_path, get_now_timestamp()],
+            (user, path, timestamp, bytes, hash) \
+            values (?1, ?2, ?3, ?4, ?5)",
+            params![user, song_path, get_now_timestamp(), bytes, hash],
         ) {
             Ok(_) => true,
             Err(e) => {
@@ -64,6 +89,120 @@ impl Metrics {
         }
     }
 
+    /// total bytes the user has uploaded since the given timestamp.
+    pub fn get_uploaded_bytes(&self, user: &str, since: i64) -> Option<u64> {
+        self.get_conn()
+            .query_row(
+                "select coalesce(sum(bytes), 0) from songUploads where user=?1 and timestamp>=?2",
+                params![user, since],
+                |row| row.get(0),
+            )
+            .inspect_err(|e| println!("Failed to get uploaded bytes: {:?}", e))
+            .ok()
+    }
+
+    /// records the hash of a song that is already in the library.
+    /// songs that were never uploaded through us get a row with no user and no bytes.
+    pub fn note_hash(&self, song_path: &String, hash: &str) -> bool {
+        match self.get_conn().execute(
+            "insert into songUploads \
+            (user, path, timestamp, bytes, hash) \
+            values ('', ?1, ?2, 0, ?3) \
+            on conflict (path) do update set hash=excluded.hash",
+            params![song_path, get_now_timestamp(), hash],
+        ) {
+            Ok(_) => true,
+            Err(e) => {
+                println!("Failed to note hash: {:?}", e);
+                false
+            }
+        }
+    }
+
+    /// the replaced song's row follows it to the trash, and remembers what replaced it.
+    pub fn note_replacement(
+        &self,
+        song_path: &String,
+        trash_path: &String,
+        new_path: &String,
+    ) -> bool {
+        let result = self
+            .get_conn()
+            .execute(
+                "update songUploads set path=?2, replacedBy=?3 where path=?1",
+                params![song_path, trash_path, new_path],
+            )
+            .and_then(|updated| match updated {
+                0 => self.get_conn().execute(
+                    "insert into songUploads \
+                    (user, path, timestamp, bytes, replacedBy) \
+                    values ('', ?1, ?2, 0, ?3)",
+                    params![trash_path, get_now_timestamp(), new_path],
+                ),
+                updated => Ok(updated),
+            });
+        match result {
+            Ok(_) => true,
+            Err(e) => {
+                println!("Failed to note replacement: {:?}", e);
+                false
+            }
+        }
+    }
+
+    /// paths that already have a hash recorded.
+    pub fn get_hashed_paths(&self) -> Option<HashSet<String>> {
+        let conn = self.get_conn();
+        let mut statement = conn
+            .prepare("select path from songUploads where hash!=''")
+            .inspect_err(|e| println!("Failed to prepare hashed paths query: {:?}", e))
+            .ok()?;
+        statement
+            .query_map([], |row| row.get(0))
+            .and_then(|rows| rows.collect())
+            .inspect_err(|e| println!("Failed to get hashed paths: {:?}", e))
+            .ok()
+    }
+
+    /// every song still in the library that we know of, newest first.
+    pub fn get_song_paths(&self) -> Option<Vec<String>> {
+        let conn = self.get_conn();
+        let mut statement = conn
+            .prepare(
+                "select path from songUploads where replacedBy is null order by timestamp desc",
+            )
+            .inspect_err(|e| println!("Failed to prepare song paths query: {:?}", e))
+            .ok()?;
+        statement
+            .query_map([], |row| row.get(0))
+            .and_then(|rows| rows.collect())
+            .inspect_err(|e| println!("Failed to get song paths: {:?}", e))
+            .ok()
+    }
+
+    /// every song recorded with these exact contents, oldest first.
+    pub fn get_uploads_by_hash(&self, hash: &str) -> Option<Vec<GetUploadItem>> {
+        let conn = self.get_conn();
+        let mut statement = conn
+            .prepare(
+                "select user, path, timestamp from songUploads \
+                where hash=?1 and replacedBy is null order by timestamp",
+            )
+            .inspect_err(|e| println!("Failed to prepare uploads by hash query: {:?}", e))
+            .ok()?;
+        statement
+            .query_map([hash], |row| {
+                Ok(GetUploadItem {
+                    user: row.get(0)?,
+                    path: row.get(1)?,
+                    timestamp: row.get(2)?,
+                })
+            })
+            .and_then(|rows| rows.collect())
+            .inspect_err(|e| println!("Failed to get uploads by hash: {:?}", e))
+            .ok()
+    }
+
     pub fn get_upload(&self, song_path: &String) -> Option<GetUploadItem> {
         self.get_conn()
             .query_row(
@@ -99,10 +238,62 @@ mod test {
         let path = "./testDb.db".to_string();
         let db = Metrics::new(&path);
         let unique_song_name = format!("fake song {}", OffsetDateTime::now_utc().to_string());
-        let result = db.note_upload(&unique_song_name, &"fake user".to_string());
+        let result = db.note_upload(&unique_song_name, &"fake user".to_string(), 1024, "");
         assert!(result)
     }
 
+    #[test]
+    fn test_uploaded_bytes_are_summed() {
+        let path = "./testDb.db".to_string();
+        let db = Metrics::new(&path);
+        let user = format!("quota user {}", OffsetDateTime::now_utc());
+        let since = get_now_timestamp();
+        assert_eq!(Some(0), db.get_uploaded_bytes(&user, since));
+        db.note_upload(&format!("{user} song 1"), &user, 100, "");
+        db.note_upload(&format!("{user} song 2"), &user, 50, "");
+        assert_eq!(Some(150), db.get_uploaded_bytes(&user, since));
+        assert_eq!(Some(0), db.get_uploaded_bytes(&user, since + 60));
+    }
+
+    #[test]
+    fn test_uploads_are_found_by_hash() {
+        let path = "./testDb.db".to_string();
+        let db = Metrics::new(&path);
+        let hash = sha256::digest(OffsetDateTime::now_utc().to_string());
+        let song = format!("hashed song {hash}");
+        assert_eq!(0, db.get_uploads_by_hash(&hash).unwrap().len());
+        db.note_upload(&song, &"fake user".to_string(), 10, &hash);
+        let uploads = db.get_uploads_by_hash(&hash).unwrap();
+        assert_eq!(
+            vec![song.clone()],
+            uploads.into_iter().map(|x| x.path).collect::<Vec<_>>()
+        );
+        // backfilling keeps the uploader of songs we already know about.
+        let new_hash = sha256::digest(&song);
+        assert!(db.note_hash(&song, &new_hash));
+        assert_eq!("fake user", db.get_upload(&song).unwrap().user);
+        assert!(db.get_hashed_paths().unwrap().contains(&song));
+        let backfilled = format!("backfilled song {hash}");
+        assert!(db.note_hash(&backfilled, &hash));
+        assert_eq!("", db.get_upload(&backfilled).unwrap().user);
+    }
+
+    #[test]
+    fn test_replaced_uploads_are_not_duplicates() {
+        let path = "./testDb.db".to_string();
+        let db = Metrics::new(&path);
+        let hash = sha256::digest(format!("replaced {}", OffsetDateTime::now_utc()));
+        let song = format!("low quality song {hash}");
+        let trash = format!("trash/{song}");
+        db.note_upload(&song, &"fake user".to_string(), 10, &hash);
+        assert!(db.note_replacement(&song, &trash, &"better song".to_string()));
+        assert!(db.get_upload(&song).is_none());
+        assert_eq!("fake user", db.get_upload(&trash).unwrap().user);
+        assert_eq!(0, db.get_uploads_by_hash(&hash).unwrap().len());
+        // the song that replaced it can be uploaded at the old path.
+        assert!(db.note_upload(&song, &"fake user".to_string(), 20, ""));
+    }
+
     #[test]
     fn test_note_route() {
         let path = "./testDb.db".to_string();
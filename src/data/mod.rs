@@ -29,6 +29,32 @@ where
     results
 }
 
+/// lets tables created by older versions of the server pick up new columns.
+fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), DbErr> {
+    let columns = query_and_map(
+        conn,
+        "add_column_if_missing",
+        &format!("pragma table_info({table})"),
+        [],
+        |row| row.get::<usize, String>(1),
+    )?;
+    if columns.iter().any(|existing_column| existing_column == column) {
+        return Ok(());
+    }
+    println!("adding column {column} to {table}");
+    conn.execute(
+        &format!("alter table {table} add column {column} {definition}"),
+        [],
+    )
+    .map_err(|e| DbErr::PrepSqlFailure(format!("error adding {column} to {table}: {e}")))?;
+    Ok(())
+}
+
 #[derive(Error, Debug)]
 pub enum DbErr {
     #[error("failed to prepare sql statement: {0}")]
@@ -2,7 +2,9 @@ use std::collections::HashSet;
 
 use rusqlite::{params, Connection, Params, Row};
 
-use crate::time_utils::get_now_timestamp;
+use crate::{
+    config::secrets_config::Role, data::add_column_if_missing, time_utils::get_now_timestamp,
+};
 
 pub struct OperationalData {
     conn: Connection,
@@ -25,6 +27,23 @@ impl OperationalData {
                 [],
             )
             .expect("could not create table :(");
+        add_column_if_missing(
+            me.get_conn(),
+            "uploadDeclaration",
+            "user",
+            "TEXT not null default ''",
+        )
+        .expect("could not add user to uploadDeclaration");
+        // the song headers are kept so the final path can be decided once the tags are readable.
+        for column in ["artist", "album", "fileName"] {
+            add_column_if_missing(
+                me.get_conn(),
+                "uploadDeclaration",
+                column,
+                "TEXT not null default ''",
+            )
+            .expect("could not add song headers to uploadDeclaration");
+        }
         me.get_conn()
             .execute(
                 "create table if not exists uploadPart \
@@ -55,6 +74,146 @@ impl OperationalData {
                 [],
             )
             .expect("could not create lastKnownSong");
+        me.get_conn()
+            .execute(
+                "create table if not exists apiToken \
+                (tokenHash TEXT not null PRIMARY KEY, \
+                username TEXT not null, \
+                timestamp DATE not null, \
+                expiresAt DATE not null, \
+                lastUsed DATE, \
+                revoked INTEGER not null default 0)",
+                [],
+            )
+            .expect("could not create apiToken");
+        me.get_conn()
+            .execute(
+                "create table if not exists authFailure \
+                (username TEXT not null, \
+                ip TEXT not null, \
+                reason TEXT not null, \
+                timestamp DATE not null)",
+                [],
+            )
+            .expect("could not create authFailure");
+        me.get_conn()
+            .execute(
+                "create table if not exists invite \
+                (codeHash TEXT not null PRIMARY KEY, \
+                role TEXT not null, \
+                createdBy TEXT not null, \
+                timestamp DATE not null, \
+                expiresAt DATE not null, \
+                redeemedBy TEXT, \
+                redeemedAt DATE)",
+                [],
+            )
+            .expect("could not create invite");
+        me.get_conn()
+            .execute(
+                "create table if not exists managedUser \
+                (username TEXT not null PRIMARY KEY, \
+                passwordHash TEXT not null, \
+                role TEXT not null, \
+                invitedBy TEXT not null, \
+                timestamp DATE not null)",
+                [],
+            )
+            .expect("could not create managedUser");
+        // names only differing in case would be confusing, but older dbs may already have some.
+        let _ = me
+            .get_conn()
+            .execute(
+                "create unique index if not exists managedUserName \
+                on managedUser (lower(username))",
+                [],
+            )
+            .inspect_err(|e| println!("could not create managedUserName index: {e}"));
+        me.get_conn()
+            .execute(
+                "create table if not exists coverArtExtraction \
+                (songPath TEXT not null, \
+                result TEXT not null, \
+                coverPath TEXT not null, \
+                timestamp DATE not null)",
+                [],
+            )
+            .expect("could not create coverArtExtraction");
+        me.get_conn()
+            .execute(
+                "create table if not exists quarantinedUpload \
+                (id INTEGER PRIMARY KEY AUTOINCREMENT, \
+                user TEXT not null, \
+                songPath TEXT not null, \
+                quarantinePath TEXT not null, \
+                size INTEGER not null, \
+                hash TEXT not null, \
+                artist TEXT not null, \
+                album TEXT not null, \
+                title TEXT not null, \
+                status TEXT not null, \
+                reason TEXT not null default '', \
+                reviewedBy TEXT, \
+                reviewedAt DATE, \
+                timestamp DATE not null)",
+                [],
+            )
+            .expect("could not create quarantinedUpload");
+        me.get_conn()
+            .execute(
+                "create table if not exists uploadBatch \
+                (id TEXT not null PRIMARY KEY, \
+                user TEXT not null, \
+                artist TEXT not null, \
+                album TEXT not null, \
+                status TEXT not null, \
+                expiresAt DATE not null, \
+                timestamp DATE not null)",
+                [],
+            )
+            .expect("could not create uploadBatch");
+        me.get_conn()
+            .execute(
+                "create table if not exists uploadBatchFile \
+                (batchId TEXT not null, \
+                fileName TEXT not null, \
+                status TEXT not null, \
+                PRIMARY KEY (batchId, fileName))",
+                [],
+            )
+            .expect("could not create uploadBatchFile");
+        add_column_if_missing(
+            me.get_conn(),
+            "uploadBatchFile",
+            "songDir",
+            "TEXT not null default ''",
+        )
+        .expect("could not add songDir to uploadBatchFile");
+        me.get_conn()
+            .execute(
+                "create table if not exists scanRequest \
+                (path TEXT not null PRIMARY KEY, \
+                reason TEXT not null, \
+                requests INTEGER not null, \
+                timestamp DATE not null)",
+                [],
+            )
+            .expect("could not create scanRequest");
+        me.get_conn()
+            .execute(
+                "create table if not exists alias \
+                (kind TEXT not null, \
+                artist TEXT not null, \
+                artistKey TEXT not null, \
+                alias TEXT not null, \
+                aliasKey TEXT not null, \
+                canonical TEXT not null, \
+                createdBy TEXT not null, \
+                timestamp DATE not null, \
+                PRIMARY KEY (kind, artistKey, aliasKey))",
+                [],
+            )
+            .expect("could not create alias");
         me
     }
 
@@ -70,6 +229,8 @@ impl OperationalData {
         declared_size_bytes: u32,
         part_size_bytes: u32,
         path: String,
+        user: String,
+        song: DeclaredSong,
     ) -> Option<UploadDeclarationItem> {
         let key = Self::build_key(&hash);
         // check if the upload is new.
@@ -79,15 +240,19 @@ impl OperationalData {
         let timestamp = get_now_timestamp();
         match self.get_conn().execute(
             "insert into uploadDeclaration \
-            (key, hash, declaredSize, partSize, path, timestamp) \
-            values (?1, ?2, ?3, ?4, ?5, ?6)",
+            (key, hash, declaredSize, partSize, path, timestamp, user, artist, album, fileName) \
+            values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
             params![
                 key,
                 hash,
                 declared_size_bytes,
                 part_size_bytes,
                 path,
-                timestamp
+                timestamp,
+                user,
+                song.artist,
+                song.album,
+                song.file_name
             ],
         ) {
             Ok(n) if n == 1 => Some(UploadDeclarationItem {
@@ -97,6 +262,8 @@ impl OperationalData {
                 part_size: part_size_bytes,
                 path,
                 timestamp,
+                user,
+                song,
             }),
             Ok(n) => {
                 println!("error creating upload declaration: did not get expected 1 row, created {n} rows");
@@ -116,7 +283,8 @@ impl OperationalData {
     pub fn get_upload_declaration(&self, key: &str) -> Option<UploadDeclarationItem> {
         self.get_conn()
             .query_row(
-                "select key, hash, declaredSize, partSize, path, timestamp \
+                "select key, hash, declaredSize, partSize, path, timestamp, user, \
+                    artist, album, fileName \
                     from uploadDeclaration where key=?1",
                 params![key],
                 |row| {
@@ -127,6 +295,12 @@ impl OperationalData {
                         part_size: row.get(3)?,
                         path: row.get(4)?,
                         timestamp: row.get(5)?,
+                        user: row.get(6)?,
+                        song: DeclaredSong {
+                            artist: row.get(7)?,
+                            album: row.get(8)?,
+                            file_name: row.get(9)?,
+                        },
                     })
                 },
             )
@@ -381,6 +555,683 @@ impl OperationalData {
             .ok()
     }
 
+    pub fn create_api_token(
+        &self,
+        token_hash: &str,
+        username: &str,
+        expires_at: i64,
+    ) -> Option<ApiTokenItem> {
+        let timestamp = get_now_timestamp();
+        match self.get_conn().execute(
+            "insert into apiToken \
+            (tokenHash, username, timestamp, expiresAt) \
+            values (?1, ?2, ?3, ?4)",
+            params![token_hash, username, timestamp, expires_at],
+        ) {
+            Ok(1) => Some(ApiTokenItem {
+                token_hash: token_hash.to_string(),
+                username: username.to_string(),
+                timestamp,
+                expires_at,
+                last_used: None,
+                revoked: false,
+            }),
+            Ok(n) => {
+                println!("error creating api token: did not get expected 1 row, created {n} rows");
+                None
+            }
+            Err(e) => {
+                println!("error creating api token: {e}");
+                None
+            }
+        }
+    }
+
+    pub fn get_api_token(&self, token_hash: &str) -> Option<ApiTokenItem> {
+        self.get_conn()
+            .query_row(
+                "select tokenHash, username, timestamp, expiresAt, lastUsed, revoked \
+                    from apiToken where tokenHash=?1",
+                params![token_hash],
+                |row| {
+                    Ok(ApiTokenItem {
+                        token_hash: row.get(0)?,
+                        username: row.get(1)?,
+                        timestamp: row.get(2)?,
+                        expires_at: row.get(3)?,
+                        last_used: row.get(4)?,
+                        revoked: row.get(5)?,
+                    })
+                },
+            )
+            .ok()
+    }
+
+    pub fn note_api_token_used(&self, token_hash: &str) -> Option<usize> {
+        self.get_conn()
+            .execute(
+                "update apiToken set lastUsed=?1 where tokenHash=?2",
+                params![get_now_timestamp(), token_hash],
+            )
+            .inspect_err(|e| println!("failed to note api token use: {e}"))
+            .ok()
+    }
+
+    pub fn revoke_api_token(&self, token_hash: &str, username: &str) -> Option<usize> {
+        self.get_conn()
+            .execute(
+                "update apiToken set revoked=1 where tokenHash=?1 and username=?2",
+                params![token_hash, username],
+            )
+            .inspect_err(|e| println!("failed to revoke api token: {e}"))
+            .ok()
+    }
+
+    pub fn revoke_user_api_tokens(&self, username: &str) -> Option<usize> {
+        self.get_conn()
+            .execute(
+                "update apiToken set revoked=1 where username=?1 and revoked=0",
+                params![username],
+            )
+            .inspect_err(|e| println!("failed to revoke api tokens for {username}: {e}"))
+            .ok()
+    }
+
+    pub fn note_auth_failure(
+        &self,
+        username: &str,
+        ip: &str,
+        reason: &str,
+        timestamp: i64,
+    ) -> Option<usize> {
+        self.get_conn()
+            .execute(
+                "insert into authFailure \
+                (username, ip, reason, timestamp) \
+                values (?1, ?2, ?3, ?4)",
+                params![username, ip, reason, timestamp],
+            )
+            .inspect_err(|e| println!("failed to note auth failure: {e}"))
+            .ok()
+    }
+
+    pub fn prune_auth_failures(&self, before: i64) -> Option<usize> {
+        self.get_conn()
+            .execute(
+                "delete from authFailure where timestamp<?1",
+                params![before],
+            )
+            .inspect_err(|e| println!("failed to prune auth failures: {e}"))
+            .ok()
+    }
+
+    /// the last failure since the timestamp, and how many failures came within window_seconds of it.
+    pub fn get_user_auth_failure_summary(
+        &self,
+        username: &str,
+        since: i64,
+        window_seconds: i64,
+    ) -> Option<AuthFailureSummary> {
+        self.get_auth_failure_summary(
+            "select count(*), lastFailure from authFailure, \
+                (select max(timestamp) as lastFailure from authFailure \
+                    where username=?1 and timestamp>=?2) \
+                where username=?1 and timestamp>=lastFailure-?3",
+            username,
+            since,
+            window_seconds,
+        )
+    }
+
+    pub fn get_ip_auth_failure_summary(
+        &self,
+        ip: &str,
+        since: i64,
+        window_seconds: i64,
+    ) -> Option<AuthFailureSummary> {
+        self.get_auth_failure_summary(
+            "select count(*), lastFailure from authFailure, \
+                (select max(timestamp) as lastFailure from authFailure \
+                    where ip=?1 and timestamp>=?2) \
+                where ip=?1 and timestamp>=lastFailure-?3",
+            ip,
+            since,
+            window_seconds,
+        )
+    }
+
+    fn get_auth_failure_summary(
+        &self,
+        sql: &str,
+        key: &str,
+        since: i64,
+        window_seconds: i64,
+    ) -> Option<AuthFailureSummary> {
+        self.get_conn()
+            .query_row(sql, params![key, since, window_seconds], |row| {
+                Ok(AuthFailureSummary {
+                    count: row.get(0)?,
+                    last_failure: row.get(1)?,
+                })
+            })
+            .inspect_err(|e| println!("failed to summarize auth failures: {e}"))
+            .ok()
+    }
+
+    pub fn get_recent_auth_failures(&self, limit: u32) -> Option<Vec<AuthFailureItem>> {
+        self.query_and_map(
+            "get_recent_auth_failures",
+            "select username, ip, reason, timestamp from authFailure \
+                order by timestamp desc limit ?1",
+            params![limit],
+            |row| {
+                Ok(AuthFailureItem {
+                    username: row.get(0)?,
+                    ip: row.get(1)?,
+                    reason: row.get(2)?,
+                    timestamp: row.get(3)?,
+                })
+            },
+        )
+    }
+
+    pub fn create_invite(
+        &self,
+        code_hash: &str,
+        role: Role,
+        created_by: &str,
+        expires_at: i64,
+    ) -> Option<usize> {
+        self.get_conn()
+            .execute(
+                "insert into invite \
+                (codeHash, role, createdBy, timestamp, expiresAt) \
+                values (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    code_hash,
+                    role.as_str(),
+                    created_by,
+                    get_now_timestamp(),
+                    expires_at
+                ],
+            )
+            .inspect_err(|e| println!("failed to create invite: {e}"))
+            .ok()
+    }
+
+    /// marks the invite as used and creates the user in one transaction so an invite can only make one user.
+    /// returns the role of the new user, or None if the invite is unknown, expired or already redeemed.
+    pub fn redeem_invite(
+        &self,
+        code_hash: &str,
+        username: &str,
+        password_hash: &str,
+    ) -> Option<Role> {
+        let now = get_now_timestamp();
+        let transaction = self
+            .get_conn()
+            .unchecked_transaction()
+            .inspect_err(|e| println!("failed to start redeem invite transaction: {e}"))
+            .ok()?;
+        let (role, created_by) = transaction
+            .query_row(
+                "select role, createdBy from invite \
+                    where codeHash=?1 and redeemedBy is null and expiresAt>?2",
+                params![code_hash, now],
+                |row| Ok((row.get::<usize, String>(0)?, row.get::<usize, String>(1)?)),
+            )
+            .ok()?;
+        let role = role
+            .parse::<Role>()
+            .inspect_err(|e| println!("invite has a bad role: {e}"))
+            .ok()?;
+        transaction
+            .execute(
+                "update invite set redeemedBy=?1, redeemedAt=?2 where codeHash=?3",
+                params![username, now, code_hash],
+            )
+            .inspect_err(|e| println!("failed to redeem invite: {e}"))
+            .ok()?;
+        transaction
+            .execute(
+                "insert into managedUser \
+                (username, passwordHash, role, invitedBy, timestamp) \
+                values (?1, ?2, ?3, ?4, ?5)",
+                params![username, password_hash, role.as_str(), created_by, now],
+            )
+            .inspect_err(|e| println!("failed to create managed user {username}: {e}"))
+            .ok()?;
+        transaction
+            .commit()
+            .inspect_err(|e| println!("failed to commit redeem invite: {e}"))
+            .ok()?;
+        Some(role)
+    }
+
+    pub fn note_cover_art_extraction(
+        &self,
+        song_path: &str,
+        result: &str,
+        cover_path: &str,
+    ) -> Option<usize> {
+        self.get_conn()
+            .execute(
+                "insert into coverArtExtraction \
+                (songPath, result, coverPath, timestamp) \
+                values (?1, ?2, ?3, ?4)",
+                params![song_path, result, cover_path, get_now_timestamp()],
+            )
+            .inspect_err(|e| println!("failed to note cover art extraction: {e}"))
+            .ok()
+    }
+
+    /// returns the id of the new quarantined upload.
+    pub fn quarantine_upload(&self, upload: &NewQuarantinedUpload) -> Option<i64> {
+        self.get_conn()
+            .execute(
+                "insert into quarantinedUpload \
+                (user, songPath, quarantinePath, size, hash, artist, album, title, status, timestamp) \
+                values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    upload.user,
+                    upload.song_path,
+                    upload.quarantine_path,
+                    upload.size,
+                    upload.hash,
+                    upload.artist,
+                    upload.album,
+                    upload.title,
+                    QUARANTINE_PENDING,
+                    get_now_timestamp()
+                ],
+            )
+            .inspect_err(|e| println!("failed to quarantine upload: {e}"))
+            .ok()?;
+        Some(self.get_conn().last_insert_rowid())
+    }
+
+    pub fn get_quarantined_upload(&self, id: i64) -> Option<QuarantinedUploadItem> {
+        self.query_quarantined_uploads("get_quarantined_upload", "id=?1", params![id])?
+            .pop()
+    }
+
+    pub fn get_pending_quarantined_uploads(&self) -> Option<Vec<QuarantinedUploadItem>> {
+        self.query_quarantined_uploads(
+            "get_pending_quarantined_uploads",
+            "status=?1",
+            params![QUARANTINE_PENDING],
+        )
+    }
+
+    pub fn get_user_quarantined_uploads(&self, user: &str) -> Option<Vec<QuarantinedUploadItem>> {
+        self.query_quarantined_uploads("get_user_quarantined_uploads", "user=?1", params![user])
+    }
+
+    pub fn get_pending_quarantined_uploads_by_hash(
+        &self,
+        hash: &str,
+    ) -> Option<Vec<QuarantinedUploadItem>> {
+        self.query_quarantined_uploads(
+            "get_pending_quarantined_uploads_by_hash",
+            "status=?1 and hash=?2",
+            params![QUARANTINE_PENDING, hash],
+        )
+    }
+
+    /// bytes the user has waiting for review since the timestamp.
+    pub fn get_pending_quarantined_bytes(&self, user: &str, since: i64) -> Option<u64> {
+        self.get_conn()
+            .query_row(
+                "select coalesce(sum(size), 0) from quarantinedUpload \
+                    where user=?1 and status=?2 and timestamp>=?3",
+                params![user, QUARANTINE_PENDING, since],
+                |row| row.get(0),
+            )
+            .inspect_err(|e| println!("Failed to get pending quarantined bytes: {:?}", e))
+            .ok()
+    }
+
+    /// approves or rejects a pending upload. false if it was already reviewed, so an upload
+    /// can't be both approved and rejected by two admins at once.
+    pub fn review_quarantined_upload(
+        &self,
+        id: i64,
+        approved: bool,
+        reason: &str,
+        reviewed_by: &str,
+    ) -> Option<bool> {
+        let status = match approved {
+            true => QUARANTINE_APPROVED,
+            false => QUARANTINE_REJECTED,
+        };
+        self.get_conn()
+            .execute(
+                "update quarantinedUpload set status=?1, reason=?2, reviewedBy=?3, reviewedAt=?4 \
+                    where id=?5 and status=?6",
+                params![
+                    status,
+                    reason,
+                    reviewed_by,
+                    get_now_timestamp(),
+                    id,
+                    QUARANTINE_PENDING
+                ],
+            )
+            .inspect_err(|e| println!("failed to review quarantined upload {id}: {e}"))
+            .ok()
+            .map(|updated| updated > 0)
+    }
+
+    fn query_quarantined_uploads<P: Params>(
+        &self,
+        title: &str,
+        filter: &str,
+        params: P,
+    ) -> Option<Vec<QuarantinedUploadItem>> {
+        self.query_and_map(
+            title,
+            &format!(
+                "select id, user, songPath, quarantinePath, size, hash, artist, album, title, \
+                    status, reason, timestamp from quarantinedUpload where {filter} order by id"
+            ),
+            params,
+            |row| {
+                Ok(QuarantinedUploadItem {
+                    id: row.get(0)?,
+                    user: row.get(1)?,
+                    song_path: row.get(2)?,
+                    quarantine_path: row.get(3)?,
+                    size: row.get(4)?,
+                    hash: row.get(5)?,
+                    artist: row.get(6)?,
+                    album: row.get(7)?,
+                    title: row.get(8)?,
+                    status: row.get(9)?,
+                    reason: row.get(10)?,
+                    timestamp: row.get(11)?,
+                })
+            },
+        )
+    }
+
+    /// creates the batch and its expected files in one transaction.
+    pub fn create_batch(
+        &self,
+        id: &str,
+        user: &str,
+        artist: &str,
+        album: &str,
+        expires_at: i64,
+        file_names: &[String],
+    ) -> Option<()> {
+        let transaction = self
+            .get_conn()
+            .unchecked_transaction()
+            .inspect_err(|e| println!("failed to start create batch transaction: {e}"))
+            .ok()?;
+        transaction
+            .execute(
+                "insert into uploadBatch \
+                (id, user, artist, album, status, expiresAt, timestamp) \
+                values (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    id,
+                    user,
+                    artist,
+                    album,
+                    BATCH_OPEN,
+                    expires_at,
+                    get_now_timestamp()
+                ],
+            )
+            .inspect_err(|e| println!("failed to create batch: {e}"))
+            .ok()?;
+        for file_name in file_names {
+            transaction
+                .execute(
+                    "insert into uploadBatchFile (batchId, fileName, status) values (?1, ?2, ?3)",
+                    params![id, file_name, BATCH_FILE_EXPECTED],
+                )
+                .inspect_err(|e| println!("failed to add {file_name} to batch: {e}"))
+                .ok()?;
+        }
+        transaction
+            .commit()
+            .inspect_err(|e| println!("failed to commit create batch: {e}"))
+            .ok()
+    }
+
+    pub fn get_batch(&self, id: &str) -> Option<BatchItem> {
+        self.query_batches("get_batch", "id=?1", params![id])?.pop()
+    }
+
+    pub fn get_expired_batches(&self, now: i64) -> Option<Vec<BatchItem>> {
+        self.query_batches(
+            "get_expired_batches",
+            "status=?1 and expiresAt<=?2",
+            params![BATCH_OPEN, now],
+        )
+    }
+
+    pub fn get_batch_files(&self, id: &str) -> Option<Vec<BatchFileItem>> {
+        self.query_and_map(
+            "get_batch_files",
+            "select fileName, status, songDir from uploadBatchFile \
+                where batchId=?1 order by fileName",
+            params![id],
+            |row| {
+                Ok(BatchFileItem {
+                    file_name: row.get(0)?,
+                    status: row.get(1)?,
+                    song_dir: row.get(2)?,
+                })
+            },
+        )
+    }
+
+    pub fn finish_batch_file(
+        &self,
+        id: &str,
+        file_name: &str,
+        status: &str,
+        song_dir: &str,
+    ) -> Option<usize> {
+        self.get_conn()
+            .execute(
+                "update uploadBatchFile set status=?1, songDir=?2 where batchId=?3 and fileName=?4",
+                params![status, song_dir, id, file_name],
+            )
+            .inspect_err(|e| println!("failed to finish {file_name} in batch {id}: {e}"))
+            .ok()
+    }
+
+    /// false if the batch was already closed, so only one caller ever triggers its scan.
+    pub fn close_batch(&self, id: &str, status: &str) -> Option<bool> {
+        self.get_conn()
+            .execute(
+                "update uploadBatch set status=?1 where id=?2 and status=?3",
+                params![status, id, BATCH_OPEN],
+            )
+            .inspect_err(|e| println!("failed to close batch {id}: {e}"))
+            .ok()
+            .map(|updated| updated > 0)
+    }
+
+    fn query_batches<P: Params>(
+        &self,
+        title: &str,
+        filter: &str,
+        params: P,
+    ) -> Option<Vec<BatchItem>> {
+        self.query_and_map(
+            title,
+            &format!(
+                "select id, user, artist, album, status, expiresAt from uploadBatch where {filter}"
+            ),
+            params,
+            |row| {
+                Ok(BatchItem {
+                    id: row.get(0)?,
+                    user: row.get(1)?,
+                    artist: row.get(2)?,
+                    album: row.get(3)?,
+                    status: row.get(4)?,
+                    expires_at: row.get(5)?,
+                })
+            },
+        )
+    }
+
+    /// asking again for a path already queued keeps its original time, so bursts can't delay it.
+    pub fn queue_scan(&self, path: &str, reason: &str) -> Option<usize> {
+        self.get_conn()
+            .execute(
+                "insert into scanRequest (path, reason, requests, timestamp) \
+                values (?1, ?2, 1, ?3) \
+                on conflict(path) do update set reason=?2, requests=requests+1",
+                params![path, reason, get_now_timestamp()],
+            )
+            .inspect_err(|e| println!("failed to queue a scan of {path}: {e}"))
+            .ok()
+    }
+
+    pub fn get_scan_requests(&self) -> Option<Vec<ScanRequestItem>> {
+        self.query_and_map(
+            "get_scan_requests",
+            "select path, reason, requests, timestamp from scanRequest order by timestamp",
+            [],
+            |row| {
+                Ok(ScanRequestItem {
+                    path: row.get(0)?,
+                    reason: row.get(1)?,
+                    requests: row.get(2)?,
+                    timestamp: row.get(3)?,
+                })
+            },
+        )
+    }
+
+    pub fn remove_scan_request(&self, path: &str) -> Option<usize> {
+        self.get_conn()
+            .execute("delete from scanRequest where path=?1", params![path])
+            .inspect_err(|e| println!("failed to remove the scan request for {path}: {e}"))
+            .ok()
+    }
+
+    /// returns false when the alias already exists.
+    pub fn add_alias(&self, alias: &NewAlias) -> Option<bool> {
+        self.get_conn()
+            .execute(
+                "insert into alias \
+                (kind, artist, artistKey, alias, aliasKey, canonical, createdBy, timestamp) \
+                values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8) on conflict do nothing",
+                params![
+                    alias.kind,
+                    alias.artist,
+                    alias.artist_key,
+                    alias.alias,
+                    alias.alias_key,
+                    alias.canonical,
+                    alias.created_by,
+                    get_now_timestamp()
+                ],
+            )
+            .inspect_err(|e| println!("failed to add alias {}: {e}", alias.alias))
+            .ok()
+            .map(|inserted| inserted > 0)
+    }
+
+    /// an alias for this artist comes before one for any artist, which has an empty artist key.
+    pub fn get_canonical_names(
+        &self,
+        kind: &str,
+        artist_key: &str,
+        alias_key: &str,
+    ) -> Option<Vec<String>> {
+        self.query_and_map(
+            "get_canonical_names",
+            "select canonical from alias where kind=?1 and aliasKey=?2 and artistKey in (?3, '') \
+            order by artistKey desc",
+            params![kind, alias_key, artist_key],
+            |row| row.get(0),
+        )
+    }
+
+    pub fn get_aliases(&self) -> Option<Vec<AliasItem>> {
+        self.query_and_map(
+            "get_aliases",
+            "select kind, artist, artistKey, alias, aliasKey, canonical, createdBy, timestamp \
+            from alias order by kind desc, canonical, alias",
+            [],
+            |row| {
+                Ok(AliasItem {
+                    kind: row.get(0)?,
+                    artist: row.get(1)?,
+                    artist_key: row.get(2)?,
+                    alias: row.get(3)?,
+                    alias_key: row.get(4)?,
+                    canonical: row.get(5)?,
+                    created_by: row.get(6)?,
+                    timestamp: row.get(7)?,
+                })
+            },
+        )
+    }
+
+    /// returns false when there was no such alias.
+    pub fn remove_alias(&self, kind: &str, artist_key: &str, alias_key: &str) -> Option<bool> {
+        self.get_conn()
+            .execute(
+                "delete from alias where kind=?1 and artistKey=?2 and aliasKey=?3",
+                params![kind, artist_key, alias_key],
+            )
+            .inspect_err(|e| println!("failed to remove alias {alias_key}: {e}"))
+            .ok()
+            .map(|removed| removed > 0)
+    }
+
+    pub fn has_managed_user_ignoring_case(&self, username: &str) -> bool {
+        match self.get_conn().query_row(
+            "select count(*) from managedUser where lower(username)=lower(?1)",
+            params![username],
+            |row| row.get::<usize, usize>(0),
+        ) {
+            Ok(count) => count > 0,
+            Err(e) => {
+                println!("failed to look up managed user {username}: {e}");
+                false
+            }
+        }
+    }
+
+    /// returns false when there was no such user.
+    pub fn remove_managed_user(&self, username: &str) -> Option<bool> {
+        self.get_conn()
+            .execute(
+                "delete from managedUser where username=?1",
+                params![username],
+            )
+            .inspect_err(|e| println!("failed to remove managed user {username}: {e}"))
+            .ok()
+            .map(|removed| removed > 0)
+    }
+
+    pub fn get_managed_user(&self, username: &str) -> Option<ManagedUserItem> {
+        self.get_conn()
+            .query_row(
+                "select username, passwordHash, role from managedUser where username=?1",
+                params![username],
+                |row| {
+                    Ok(ManagedUserItem {
+                        username: row.get(0)?,
+                        password_hash: row.get(1)?,
+                        role: row.get(2)?,
+                    })
+                },
+            )
+            .ok()
+    }
+
     fn query_and_map<T, P, F>(
         &self,
         title: &str,
@@ -419,6 +1270,131 @@ pub struct LastKnownPlaylistState {
     pub song_ids: HashSet<String>,
 }
 
+#[allow(unused)]
+pub struct ApiTokenItem {
+    pub token_hash: String,
+    pub username: String,
+    pub timestamp: i64,
+    pub expires_at: i64,
+    pub last_used: Option<i64>,
+    pub revoked: bool,
+}
+
+impl ApiTokenItem {
+    pub fn is_usable(&self, now: i64) -> bool {
+        !self.revoked && now < self.expires_at
+    }
+}
+
+#[allow(unused)]
+pub struct ManagedUserItem {
+    pub username: String,
+    pub password_hash: String,
+    pub role: String,
+}
+
+pub const QUARANTINE_PENDING: &str = "pending";
+pub const QUARANTINE_APPROVED: &str = "approved";
+pub const QUARANTINE_REJECTED: &str = "rejected";
+
+/// a moderated user's upload, waiting in quarantine_dir to be moved to song_path.
+/// artist, album and title are from the song's tags, empty when it has none.
+pub struct NewQuarantinedUpload {
+    pub user: String,
+    pub song_path: String,
+    pub quarantine_path: String,
+    pub size: u64,
+    pub hash: String,
+    pub artist: String,
+    pub album: String,
+    pub title: String,
+}
+
+pub struct QuarantinedUploadItem {
+    pub id: i64,
+    pub user: String,
+    pub song_path: String,
+    pub quarantine_path: String,
+    pub size: u64,
+    pub hash: String,
+    pub artist: String,
+    pub album: String,
+    pub title: String,
+    pub status: String,
+    pub reason: String,
+    pub timestamp: i64,
+}
+
+pub const BATCH_OPEN: &str = "open";
+pub const BATCH_COMPLETE: &str = "complete";
+pub const BATCH_TIMED_OUT: &str = "timed_out";
+pub const BATCH_FILE_EXPECTED: &str = "expected";
+pub const BATCH_FILE_UPLOADED: &str = "uploaded";
+/// the song was already in the library, or waiting in quarantine.
+pub const BATCH_FILE_SKIPPED: &str = "skipped";
+
+pub struct BatchItem {
+    pub id: String,
+    pub user: String,
+    pub artist: String,
+    pub album: String,
+    pub status: String,
+    pub expires_at: i64,
+}
+
+/// song_dir is where an uploaded file was written, empty until then or when it was quarantined.
+pub struct BatchFileItem {
+    pub file_name: String,
+    pub status: String,
+    pub song_dir: String,
+}
+
+pub const ALIAS_ARTIST: &str = "artist";
+pub const ALIAS_ALBUM: &str = "album";
+
+/// the keys are the names as they are matched, the artist is empty unless an album alias
+/// only applies to one artist.
+pub struct NewAlias {
+    pub kind: String,
+    pub artist: String,
+    pub artist_key: String,
+    pub alias: String,
+    pub alias_key: String,
+    pub canonical: String,
+    pub created_by: String,
+}
+
+pub struct AliasItem {
+    pub kind: String,
+    pub artist: String,
+    pub artist_key: String,
+    pub alias: String,
+    pub alias_key: String,
+    pub canonical: String,
+    pub created_by: String,
+    pub timestamp: i64,
+}
+
+/// a directory waiting to be scanned, an empty path is the whole library.
+pub struct ScanRequestItem {
+    pub path: String,
+    pub reason: String,
+    pub requests: u32,
+    pub timestamp: i64,
+}
+
+pub struct AuthFailureSummary {
+    pub count: u32,
+    pub last_failure: Option<i64>,
+}
+
+pub struct AuthFailureItem {
+    pub username: String,
+    pub ip: String,
+    pub reason: String,
+    pub timestamp: i64,
+}
+
 #[allow(unused)]
 pub struct UploadDeclarationItem {
     pub key: String,
@@ -427,6 +1403,15 @@ pub struct UploadDeclarationItem {
     pub part_size: u32,
     pub path: String,
     pub timestamp: i64,
+    pub user: String,
+    pub song: DeclaredSong,
+}
+
+/// the song headers sent with a declaration, empty for declarations made before they were stored.
+pub struct DeclaredSong {
+    pub artist: String,
+    pub album: String,
+    pub file_name: String,
 }
 
 impl UploadDeclarationItem {
@@ -495,4 +1480,141 @@ mod test {
         let sorted_indices = thing.into_iter().map(|item| item.index).collect::<Vec<_>>();
         assert_eq!(sorted_indices, vec![0, 1, 2, 3, 4, 5, 6]);
     }
+
+    #[test]
+    fn test_quarantined_uploads_are_reviewed_once() {
+        let db = OperationalData::new(&"./testDb.db".to_string());
+        let hash = sha256::digest(format!("quarantined {}", get_now_timestamp()));
+        let id = db
+            .quarantine_upload(&NewQuarantinedUpload {
+                user: "moderated user".to_string(),
+                song_path: "artist/album/song.mp3".to_string(),
+                quarantine_path: "quarantine/song.mp3".to_string(),
+                size: 10,
+                hash: hash.clone(),
+                artist: String::new(),
+                album: String::new(),
+                title: String::new(),
+            })
+            .unwrap();
+        assert_eq!(
+            1,
+            db.get_pending_quarantined_uploads_by_hash(&hash)
+                .unwrap()
+                .len()
+        );
+        assert_eq!(
+            Some(10),
+            db.get_pending_quarantined_bytes("moderated user", get_now_timestamp())
+        );
+        assert_eq!(
+            Some(true),
+            db.review_quarantined_upload(id, false, "too loud", "admin")
+        );
+        assert_eq!(
+            Some(0),
+            db.get_pending_quarantined_bytes("moderated user", get_now_timestamp())
+        );
+        assert_eq!(
+            Some(false),
+            db.review_quarantined_upload(id, true, "", "admin")
+        );
+        let upload = db.get_quarantined_upload(id).unwrap();
+        assert_eq!(QUARANTINE_REJECTED, upload.status);
+        assert_eq!("too loud", upload.reason);
+        assert!(db
+            .get_pending_quarantined_uploads_by_hash(&hash)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_batches_close_once() {
+        let db = OperationalData::new(&"./testDb.db".to_string());
+        let id = sha256::digest(format!("batch {}", get_now_timestamp()));
+        let files = vec!["01 one.mp3".to_string(), "02 two.mp3".to_string()];
+        db.create_batch(&id, "user", "artist", "album", 0, &files)
+            .unwrap();
+        db.finish_batch_file(&id, "02 two.mp3", BATCH_FILE_UPLOADED, "artist/album");
+        let statuses = db
+            .get_batch_files(&id)
+            .unwrap()
+            .into_iter()
+            .map(|file| file.status)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![BATCH_FILE_EXPECTED, BATCH_FILE_UPLOADED], statuses);
+        assert!(db
+            .get_expired_batches(1)
+            .unwrap()
+            .iter()
+            .any(|batch| batch.id == id));
+        assert_eq!(Some(true), db.close_batch(&id, BATCH_TIMED_OUT));
+        assert_eq!(Some(false), db.close_batch(&id, BATCH_COMPLETE));
+        assert_eq!(BATCH_TIMED_OUT, db.get_batch(&id).unwrap().status);
+    }
+
+    #[test]
+    fn test_managed_users_ignore_case() {
+        let db = OperationalData::new(&"./testDb.db".to_string());
+        let username = format!("Alice{}", rand::random::<u32>());
+        let expires_at = get_now_timestamp() + 60;
+        for code in ["first", "second"] {
+            let code_hash = format!("{username} {code}");
+            db.create_invite(&code_hash, Role::Uploader, "admin", expires_at)
+                .unwrap();
+        }
+        assert!(db
+            .redeem_invite(&format!("{username} first"), &username, "hash")
+            .is_some());
+        assert!(db.has_managed_user_ignoring_case(&username.to_lowercase()));
+        assert!(db
+            .redeem_invite(
+                &format!("{username} second"),
+                &username.to_lowercase(),
+                "hash"
+            )
+            .is_none());
+        assert_eq!(Some(true), db.remove_managed_user(&username));
+        assert_eq!(Some(false), db.remove_managed_user(&username));
+        assert!(!db.has_managed_user_ignoring_case(&username));
+    }
+
+    #[test]
+    fn test_album_aliases_prefer_their_artist() {
+        let db = OperationalData::new(&"./testDb.db".to_string());
+        let alias_key = sha256::digest(format!("alias {}", get_now_timestamp()));
+        let build_alias = |artist: &str, canonical: &str| NewAlias {
+            kind: ALIAS_ALBUM.to_string(),
+            artist: artist.to_string(),
+            artist_key: artist.to_lowercase(),
+            alias: alias_key.clone(),
+            alias_key: alias_key.clone(),
+            canonical: canonical.to_string(),
+            created_by: "admin".to_string(),
+        };
+        assert_eq!(Some(true), db.add_alias(&build_alias("", "Any Artist")));
+        assert_eq!(Some(true), db.add_alias(&build_alias("Artist", "Artist's")));
+        assert_eq!(Some(false), db.add_alias(&build_alias("Artist", "Another")));
+        assert_eq!(
+            Some(vec!["Artist's".to_string(), "Any Artist".to_string()]),
+            db.get_canonical_names(ALIAS_ALBUM, "artist", &alias_key)
+        );
+        assert_eq!(
+            Some(vec!["Any Artist".to_string()]),
+            db.get_canonical_names(ALIAS_ALBUM, "other", &alias_key)
+        );
+        assert_eq!(
+            Some(Vec::<String>::new()),
+            db.get_canonical_names(ALIAS_ARTIST, "", &alias_key)
+        );
+        assert_eq!(
+            Some(true),
+            db.remove_alias(ALIAS_ALBUM, "artist", &alias_key)
+        );
+        assert_eq!(
+            Some(false),
+            db.remove_alias(ALIAS_ALBUM, "artist", &alias_key)
+        );
+        assert_eq!(Some(true), db.remove_alias(ALIAS_ALBUM, "", &alias_key));
+    }
 }
@@ -1,15 +1,27 @@
-use rocket::{data::ByteUnit, Data};
+use rocket::{
+    data::ByteUnit,
+    tokio::{fs::File as AsyncFile, io::AsyncWrite},
+    Data,
+};
+use sha2::{Digest, Sha256};
 use std::{
     fs::{self, File},
-    io::{Read, Write},
-    path::PathBuf,
+    io::{self, BufReader, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{Context, Poll},
 };
 
-use crate::model::MusicUploaderError;
+use crate::{
+    model::MusicUploaderError,
+    tags::{read_tags, Tags},
+};
 
-pub fn check_hash(expected_hash: &String, data: &[u8]) -> Result<(), MusicUploaderError> {
-    let computed_hash = sha256::digest(data);
-    if expected_hash == &computed_hash {
+pub fn check_hash(
+    expected_hash: &String,
+    computed_hash: &String,
+) -> Result<(), MusicUploaderError> {
+    if expected_hash == computed_hash {
         Ok(())
     } else {
         Err(MusicUploaderError::ConstraintViolation(
@@ -18,45 +30,404 @@ pub fn check_hash(expected_hash: &String, data: &[u8]) -> Result<(), MusicUpload
     }
 }
 
-pub async fn read_in_complete_data(
+/// an incoming body that has been written to temp_file_dir.
+/// the temp file is removed when this is dropped, so it only outlives the request if persisted.
+pub struct StreamedUpload {
+    pub hash: String,
+    pub size: u64,
+    temp_path: PathBuf,
+}
+
+impl StreamedUpload {
+    /// makes the upload visible at file_path, failing if something already exists there.
+    /// the temp file is already synced, so linking it in place is enough to be atomic and durable.
+    pub fn persist(self, file_path: &Path) -> Result<(), MusicUploaderError> {
+        match fs::hard_link(&self.temp_path, file_path) {
+            Ok(()) => {
+                sync_dir(file_path.parent().unwrap_or(Path::new(".")));
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                Err(MusicUploaderError::InternalServerError(e.to_string()))
+            }
+            // temp_file_dir can live on a different filesystem than the destination.
+            Err(_) => write_new_file_atomically(file_path, |file| {
+                io::copy(&mut File::open(&self.temp_path)?, file).map(|_| ())
+            }),
+        }
+    }
+
+    /// like persist, but replaces whatever is at file_path.
+    pub fn persist_replacing(self, file_path: &Path) -> Result<(), MusicUploaderError> {
+        replace_file_atomically(file_path, |file| {
+            io::copy(&mut File::open(&self.temp_path)?, file).map(|_| ())
+        })
+    }
+
+    /// reads up to max_length bytes from the start of the upload.
+    pub fn read_start(&self, max_length: usize) -> Result<Vec<u8>, MusicUploaderError> {
+        let mut start = Vec::with_capacity(max_length);
+        File::open(&self.temp_path)
+            .and_then(|file| file.take(max_length as u64).read_to_end(&mut start))
+            .map_err(|e| {
+                MusicUploaderError::InternalServerError(format!("Failed to read temp file: {e}"))
+            })?;
+        Ok(start)
+    }
+
+    /// opens the temp file for reading from the start.
+    pub fn open(&self) -> io::Result<File> {
+        File::open(&self.temp_path)
+    }
+
+    pub fn read_tags(&self) -> Option<Tags> {
+        File::open(&self.temp_path)
+            .inspect_err(|e| println!("failed to open temp upload to read tags: {e}"))
+            .ok()
+            .and_then(|file| read_tags(&mut BufReader::new(file)))
+    }
+}
+
+impl Drop for StreamedUpload {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.temp_path) {
+            println!("failed to remove temp upload {:?}: {e}", self.temp_path);
+        }
+    }
+}
+
+/// hashes bytes as they are written so the body never has to be held in memory.
+struct HashingFile {
+    file: AsyncFile,
+    hasher: Sha256,
+}
+
+impl AsyncWrite for HashingFile {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.file).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            this.hasher.update(&buf[..written]);
+        }
+        poll
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().file).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().file).poll_shutdown(cx)
+    }
+}
+
+pub async fn stream_to_temp_file(
     data: Data<'_>,
     max_bytes: ByteUnit,
-) -> Result<Vec<u8>, MusicUploaderError> {
-    let incoming_data = data.open(max_bytes);
-    let bytes = incoming_data
-        .into_bytes()
+    temp_file_dir: &String,
+) -> Result<StreamedUpload, MusicUploaderError> {
+    let temp_path = build_temp_path(temp_file_dir);
+    let file = AsyncFile::options()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)
         .await
-        .map_err(|e| MusicUploaderError::InternalServerError(e.to_string()))?;
-    if !bytes.is_complete() {
+        .map_err(|e| {
+            MusicUploaderError::InternalServerError(format!("Failed to create temp file: {e}"))
+        })?;
+    let mut writer = HashingFile {
+        file,
+        hasher: Sha256::new(),
+    };
+    let written = data.open(max_bytes).stream_to(&mut writer).await;
+    // from here on the temp file is cleaned up on every return path.
+    let mut upload = StreamedUpload {
+        hash: format!("{:x}", writer.hasher.finalize()),
+        size: 0,
+        temp_path,
+    };
+    let written = written.map_err(|e| MusicUploaderError::InternalServerError(e.to_string()))?;
+    writer.file.sync_all().await.map_err(|e| {
+        MusicUploaderError::InternalServerError(format!("Failed to write temp file: {e}"))
+    })?;
+    if !written.complete {
         return Err(MusicUploaderError::ConstraintViolation(
             "File uploaded is too large".to_string(),
         ));
     }
-    Ok(bytes.value)
+    upload.size = written.written;
+    Ok(upload)
 }
 
-pub fn write_bytes_to_new_file(file_path: PathBuf, bytes: &[u8]) -> Result<(), MusicUploaderError> {
-    let mut file = fs::OpenOptions::new()
+/// joins files end to end into a new temp file, hashing along the way.
+pub fn concatenate_to_temp_file(
+    paths: &[PathBuf],
+    temp_file_dir: &String,
+) -> Result<StreamedUpload, MusicUploaderError> {
+    let (mut file, mut upload) = create_temp_file(temp_file_dir)?;
+    let mut hasher = Sha256::new();
+    for path in paths {
+        let mut part = File::open(path).map_err(|e| {
+            MusicUploaderError::InternalServerError(format!("Failed to read file part: {e}"))
+        })?;
+        upload.size += copy_and_hash(&mut part, &mut file, &mut hasher).map_err(|e| {
+            MusicUploaderError::InternalServerError(format!("Failed to copy file part: {e}"))
+        })?;
+    }
+    finish_temp_file(file, upload, hasher)
+}
+
+/// copies at most max_bytes from reader into a new temp file, hashing along the way.
+pub fn copy_to_temp_file<R: Read>(
+    reader: &mut R,
+    max_bytes: u64,
+    temp_file_dir: &String,
+) -> Result<StreamedUpload, MusicUploaderError> {
+    let (mut file, mut upload) = create_temp_file(temp_file_dir)?;
+    let mut hasher = Sha256::new();
+    // one byte past the limit is enough to know it is too large.
+    upload.size =
+        copy_and_hash(&mut reader.take(max_bytes + 1), &mut file, &mut hasher).map_err(|e| {
+            MusicUploaderError::InternalServerError(format!("Failed to write temp file: {e}"))
+        })?;
+    if upload.size > max_bytes {
+        return Err(MusicUploaderError::ConstraintViolation(
+            "File uploaded is too large".to_string(),
+        ));
+    }
+    finish_temp_file(file, upload, hasher)
+}
+
+/// the StreamedUpload owns the temp file from the start so it is cleaned up on every error.
+fn create_temp_file(temp_file_dir: &String) -> Result<(File, StreamedUpload), MusicUploaderError> {
+    let temp_path = build_temp_path(temp_file_dir);
+    let file = fs::OpenOptions::new()
         .write(true)
         .create_new(true)
-        .open(file_path)
-        .map_err(|e| MusicUploaderError::InternalServerError(e.to_string()))?;
-    let _ = file
-        .write_all(&bytes)
-        .map_err(|e| MusicUploaderError::InternalServerError(e.to_string()));
-    Ok(())
+        .open(&temp_path)
+        .map_err(|e| {
+            MusicUploaderError::InternalServerError(format!("Failed to create temp file: {e}"))
+        })?;
+    let upload = StreamedUpload {
+        hash: String::new(),
+        size: 0,
+        temp_path,
+    };
+    Ok((file, upload))
 }
 
-pub fn read_bytes_from_file(
-    file_path: PathBuf,
-    expected_size: usize,
-) -> Result<Vec<u8>, MusicUploaderError> {
-    let mut file = File::open(file_path).map_err(|e| {
-        MusicUploaderError::InternalServerError(format!("Failed to read file part: {e}"))
+fn copy_and_hash<R: Read>(reader: &mut R, file: &mut File, hasher: &mut Sha256) -> io::Result<u64> {
+    let mut buffer = [0u8; 64 * 1024];
+    let mut copied = 0;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(copied);
+        }
+        hasher.update(&buffer[..read]);
+        file.write_all(&buffer[..read])?;
+        copied += read as u64;
+    }
+}
+
+fn finish_temp_file(
+    file: File,
+    mut upload: StreamedUpload,
+    hasher: Sha256,
+) -> Result<StreamedUpload, MusicUploaderError> {
+    file.sync_all().map_err(|e| {
+        MusicUploaderError::InternalServerError(format!("Failed to write temp file: {e}"))
     })?;
-    let mut bytes: Vec<u8> = Vec::with_capacity(expected_size);
-    let _ = file.read_to_end(&mut bytes).map_err(|e| {
-        MusicUploaderError::InternalServerError(format!("failed to read bytes of parts file: {e}"))
+    upload.hash = format!("{:x}", hasher.finalize());
+    Ok(upload)
+}
+
+/// sha256 of a file already on disk, in the same format clients send.
+pub fn hash_file(file_path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut BufReader::new(File::open(file_path)?), &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// writes to a hidden file next to file_path, fsyncs it, then links it into place.
+/// plex's scanner skips hidden files, and the link fails rather than replacing an existing file,
+/// so file_path either doesn't exist or holds the complete contents.
+pub fn write_new_file_atomically<F>(
+    file_path: &Path,
+    write_contents: F,
+) -> Result<(), MusicUploaderError>
+where
+    F: FnOnce(&mut File) -> io::Result<()>,
+{
+    write_file_atomically(file_path, write_contents, |from, to| {
+        fs::hard_link(from, to)
+    })
+}
+
+/// like write_new_file_atomically, but renames over whatever is at file_path.
+pub fn replace_file_atomically<F>(
+    file_path: &Path,
+    write_contents: F,
+) -> Result<(), MusicUploaderError>
+where
+    F: FnOnce(&mut File) -> io::Result<()>,
+{
+    write_file_atomically(file_path, write_contents, |from, to| fs::rename(from, to))
+}
+
+fn write_file_atomically<F>(
+    file_path: &Path,
+    write_contents: F,
+    move_into_place: fn(&Path, &Path) -> io::Result<()>,
+) -> Result<(), MusicUploaderError>
+where
+    F: FnOnce(&mut File) -> io::Result<()>,
+{
+    let (Some(dir), Some(file_name)) = (file_path.parent(), file_path.file_name()) else {
+        return Err(MusicUploaderError::InternalServerError(format!(
+            "can't write to {file_path:?}, it isn't a file path"
+        )));
+    };
+    let hidden_path = dir.join(format!(
+        ".{}.{:08x}.partial",
+        file_name.to_string_lossy(),
+        rand::random::<u32>()
+    ));
+    let result = write_and_move(&hidden_path, file_path, write_contents, move_into_place);
+    // once linked, file_path keeps the contents alive on its own.
+    if let Err(e) = fs::remove_file(&hidden_path) {
+        if e.kind() != ErrorKind::NotFound {
+            println!("failed to remove partial file {hidden_path:?}: {e}");
+        }
+    }
+    result.map_err(|e| {
+        MusicUploaderError::InternalServerError(format!("Failed to write {file_path:?}: {e}"))
     })?;
-    Ok(bytes)
+    sync_dir(dir);
+    Ok(())
+}
+
+fn write_and_move<F>(
+    hidden_path: &Path,
+    file_path: &Path,
+    write_contents: F,
+    move_into_place: fn(&Path, &Path) -> io::Result<()>,
+) -> io::Result<()>
+where
+    F: FnOnce(&mut File) -> io::Result<()>,
+{
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(hidden_path)?;
+    write_contents(&mut file)?;
+    file.sync_all()?;
+    move_into_place(hidden_path, file_path)
+}
+
+/// makes a newly linked file survive a crash, not just the bytes it points at.
+fn sync_dir(dir: &Path) {
+    let _ = File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .inspect_err(|e| println!("failed to sync directory {dir:?}: {e}"));
+}
+
+fn build_temp_path(temp_file_dir: &String) -> PathBuf {
+    Path::new(temp_file_dir).join(format!("{:016x}.upload", rand::random::<u64>()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use super::*;
+
+    fn build_temp_upload(contents: &[u8]) -> StreamedUpload {
+        let temp_path = env::temp_dir().join(format!("{:016x}.upload", rand::random::<u64>()));
+        fs::write(&temp_path, contents).unwrap();
+        StreamedUpload {
+            hash: sha256::digest(contents),
+            size: contents.len() as u64,
+            temp_path,
+        }
+    }
+
+    #[test]
+    fn test_persist_moves_temp_file() {
+        let upload = build_temp_upload(b"some song");
+        let temp_path = upload.temp_path.clone();
+        let destination = env::temp_dir().join(format!("{:016x}.mp3", rand::random::<u64>()));
+        upload.persist(&destination).unwrap();
+        assert!(!temp_path.exists());
+        assert_eq!(b"some song".to_vec(), fs::read(&destination).unwrap());
+        fs::remove_file(destination).unwrap();
+    }
+
+    #[test]
+    fn test_persist_does_not_overwrite() {
+        let destination = env::temp_dir().join(format!("{:016x}.mp3", rand::random::<u64>()));
+        fs::write(&destination, b"original").unwrap();
+        let upload = build_temp_upload(b"imposter");
+        assert!(upload.persist(&destination).is_err());
+        assert_eq!(b"original".to_vec(), fs::read(&destination).unwrap());
+        fs::remove_file(destination).unwrap();
+    }
+
+    #[test]
+    fn test_persist_replacing_overwrites() {
+        let dir = env::temp_dir().join(format!("{:016x}", rand::random::<u64>()));
+        fs::create_dir(&dir).unwrap();
+        let destination = dir.join("cover.jpg");
+        fs::write(&destination, b"original").unwrap();
+        build_temp_upload(b"better")
+            .persist_replacing(&destination)
+            .unwrap();
+        assert_eq!(b"better".to_vec(), fs::read(&destination).unwrap());
+        assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_atomic_write_leaves_nothing_behind() {
+        let dir = env::temp_dir().join(format!("{:016x}", rand::random::<u64>()));
+        fs::create_dir(&dir).unwrap();
+        let destination = dir.join("song.mp3");
+        let result = write_new_file_atomically(&destination, |file| {
+            file.write_all(b"half a song")?;
+            Err(io::Error::new(ErrorKind::StorageFull, "disk is full"))
+        });
+        assert!(result.is_err());
+        assert_eq!(0, fs::read_dir(&dir).unwrap().count());
+        write_new_file_atomically(&destination, |file| file.write_all(b"whole song")).unwrap();
+        assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+        assert_eq!(b"whole song".to_vec(), fs::read(&destination).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_concatenated_parts_hash_like_the_whole() {
+        let first = build_temp_upload(b"first half ");
+        let second = build_temp_upload(b"second half");
+        let temp_dir = env::temp_dir().to_string_lossy().to_string();
+        let upload = concatenate_to_temp_file(
+            &[first.temp_path.clone(), second.temp_path.clone()],
+            &temp_dir,
+        )
+        .unwrap();
+        assert_eq!(22, upload.size);
+        assert_eq!(sha256::digest("first half second half"), upload.hash);
+        assert_eq!(upload.hash, hash_file(&upload.temp_path).unwrap());
+    }
+
+    #[test]
+    fn test_copy_respects_the_limit() {
+        let temp_dir = env::temp_dir().to_string_lossy().to_string();
+        let upload = copy_to_temp_file(&mut &b"a song"[..], 6, &temp_dir).unwrap();
+        assert_eq!(sha256::digest("a song"), upload.hash);
+        assert!(copy_to_temp_file(&mut &b"a longer song"[..], 6, &temp_dir).is_err());
+    }
 }
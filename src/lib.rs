@@ -1,27 +1,63 @@
 use activities::{
-    multipart_upload::{declare_upload::declare_upload, upload_part::upload_part},
+    alias::{aliases, create_alias, delete_alias},
+    api_token::{create_api_token, revoke_api_token, revoke_user_api_tokens},
+    auth_failures::auth_failures,
+    backfill_hashes::backfill_hashes,
+    batch::{batch_status, declare_batch},
+    cover_art::upload_cover_art,
+    invite::{create_invite, register, remove_user},
+    lyrics::upload_lyrics,
+    multipart_upload::{
+        declare_archive_upload::declare_archive_upload, declare_upload::declare_upload,
+        upload_part::upload_part,
+    },
+    quarantine::{
+        approve_quarantined_upload, list_quarantine, listen_to_quarantined_upload, my_quarantine,
+        reject_quarantined_upload,
+    },
+    quota::get_quota,
+    reload_users::reload_users,
     search::album_search,
     simple_routes::{check_auth, check_conn},
-    trigger_scan::trigger_scan,
+    trigger_scan::{scan_queue, trigger_scan},
     upload::upload,
 };
 use authenticated::Authenticator;
 use config::server_config::ServerConfig;
+use plex_auth::PlexAuthenticator;
 use rocket::{catch, catchers, fairing::AdHoc, routes, Build, Rocket};
+use services::{
+    batch_timeouts::start_batch_timeouts, scan_queue::start_scan_queue,
+    watch_secrets::start_watch_secrets,
+};
 use std::env;
 
 use crate::activities::public_playlists::public_playlists;
 
 mod activities;
+mod alias;
+mod auth_throttle;
 mod authenticated;
+mod batch;
 pub mod clients;
 mod config;
+mod content_sniffing;
+mod cover_art;
 mod data;
 mod data_validation;
+mod lyrics;
 pub mod model;
+pub mod password_utils;
+mod path_template;
 mod path_utils;
+mod plex_auth;
+mod quarantine;
+mod quota;
+mod replace;
 mod rocket_utils;
+mod scan;
 pub mod services;
+mod tags;
 mod time_utils;
 
 #[catch(401)]
@@ -29,6 +65,16 @@ fn unauthorized() -> String {
     "request is not authorized".to_string()
 }
 
+#[catch(403)]
+fn forbidden() -> String {
+    "user does not have permission for this request".to_string()
+}
+
+#[catch(429)]
+fn too_many_requests() -> String {
+    "too many failed logins, try again later".to_string()
+}
+
 pub fn build_rocket() -> Rocket<Build> {
     println!(
         "starting musicuploader server, version: {}",
@@ -38,7 +84,7 @@ pub fn build_rocket() -> Rocket<Build> {
     let authenticator = Authenticator::new()
         .expect("cannot run server without authenticator must look into issues");
     rocket::build()
-        .register("/api", catchers![unauthorized])
+        .register("/api", catchers![unauthorized, forbidden, too_many_requests])
         .mount(
             "/api",
             routes![
@@ -50,10 +96,58 @@ pub fn build_rocket() -> Rocket<Build> {
                 declare_upload,
                 upload_part,
                 public_playlists,
+                create_api_token,
+                revoke_api_token,
+                revoke_user_api_tokens,
+                reload_users,
+                auth_failures,
+                create_invite,
+                register,
+                remove_user,
+                get_quota,
+                backfill_hashes,
+                upload_cover_art,
+                upload_lyrics,
+                declare_archive_upload,
+                list_quarantine,
+                my_quarantine,
+                listen_to_quarantined_upload,
+                approve_quarantined_upload,
+                reject_quarantined_upload,
+                declare_batch,
+                batch_status,
+                scan_queue,
+                aliases,
+                create_alias,
+                delete_alias,
             ],
         )
         .attach(AdHoc::config::<ServerConfig>())
+        .attach(AdHoc::on_liftoff("watch secrets", |rocket| {
+            Box::pin(async move {
+                if let Some(authenticator) = rocket.state::<Authenticator>() {
+                    start_watch_secrets(authenticator.clone());
+                }
+            })
+        }))
+        .attach(AdHoc::on_liftoff("batch timeouts", |rocket| {
+            Box::pin(async move {
+                match rocket.figment().extract::<ServerConfig>() {
+                    Ok(server_config) => start_batch_timeouts(server_config),
+                    Err(e) => println!("ERROR: batches will never time out, bad config: {e}"),
+                }
+            })
+        }))
+        .attach(AdHoc::on_liftoff("scan queue", |rocket| {
+            Box::pin(async move {
+                match rocket.figment().extract::<ServerConfig>() {
+                    Ok(server_config) => start_scan_queue(server_config),
+                    Err(e) => println!("ERROR: plex will never be asked to scan, bad config: {e}"),
+                }
+            })
+        }))
         .manage(authenticator)
+        .manage(PlexAuthenticator::new())
 }
 
 pub fn config_env_or_panic() {
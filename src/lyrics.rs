@@ -0,0 +1,110 @@
+use crate::model::MusicUploaderError;
+
+const UTF8_BOM: &str = "\u{FEFF}";
+
+/// checks that a .lrc file is utf-8, that every line starts with well formed tags,
+/// and that at least one line is timed.
+pub fn validate_lrc(bytes: &[u8]) -> Result<(), MusicUploaderError> {
+    let text = std::str::from_utf8(bytes).map_err(|e| {
+        MusicUploaderError::ConstraintViolation(format!("lyrics are not valid utf-8: {e}"))
+    })?;
+    let text = text.strip_prefix(UTF8_BOM).unwrap_or(text);
+    let mut has_timestamp = false;
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        has_timestamp |= validate_line(line).map_err(|reason| {
+            MusicUploaderError::ConstraintViolation(format!("lyrics line {}: {reason}", index + 1))
+        })?;
+    }
+    match has_timestamp {
+        true => Ok(()),
+        false => Err(MusicUploaderError::ConstraintViolation(
+            "lyrics have no timestamps".to_string(),
+        )),
+    }
+}
+
+/// a line is one or more tags followed by the lyric. returns whether it had a timestamp.
+fn validate_line(line: &str) -> Result<bool, String> {
+    if !line.starts_with('[') {
+        return Err("does not start with a [tag]".to_string());
+    }
+    let mut has_timestamp = false;
+    let mut rest = line;
+    while let Some(tagged) = rest.strip_prefix('[') {
+        let (tag, after) = tagged
+            .split_once(']')
+            .ok_or(format!("[{tagged} is never closed"))?;
+        match tag.starts_with(|c: char| c.is_ascii_digit()) {
+            true if is_timestamp(tag) => has_timestamp = true,
+            true => return Err(format!("[{tag}] is not a valid timestamp")),
+            false => validate_id_tag(tag)?,
+        }
+        rest = after.trim_start();
+    }
+    Ok(has_timestamp)
+}
+
+/// `mm:ss`, optionally followed by hundredths or thousandths after a `.` or `:`.
+fn is_timestamp(tag: &str) -> bool {
+    let Some((minutes, seconds)) = tag.split_once(':') else {
+        return false;
+    };
+    let (seconds, fraction) = match seconds.split_once(['.', ':']) {
+        Some((seconds, fraction)) => (seconds, Some(fraction)),
+        None => (seconds, None),
+    };
+    let all_digits = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit());
+    all_digits(minutes)
+        && seconds.len() == 2
+        && all_digits(seconds)
+        && seconds < "60"
+        && fraction.is_none_or(|fraction| fraction.len() <= 3 && all_digits(fraction))
+}
+
+/// metadata like `[ar:artist]` or `[offset:+250]`.
+fn validate_id_tag(tag: &str) -> Result<(), String> {
+    let (key, value) = tag
+        .split_once(':')
+        .ok_or(format!("[{tag}] is not a timestamp or a key:value tag"))?;
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphabetic() || c == '#') {
+        return Err(format!("[{tag}] has an invalid key"));
+    }
+    if key == "offset" && value.trim().parse::<i64>().is_err() {
+        return Err(format!("[{tag}] offset is not a number of milliseconds"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_valid_lyrics() {
+        let lyrics = "\u{FEFF}[ar:Charli XCX]\n[offset:+250]\n\n[00:12.34]first line\r\n\
+            [01:02][01:30.5]chorus\n[02:03:45]\n[10:00.123]still going";
+        assert!(validate_lrc(lyrics.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_lyrics() {
+        let invalid = [
+            "[ar:Charli XCX]\n[ti:no timestamps]",
+            "[00:12.34]fine\njust words",
+            "[00:60.00]too many seconds",
+            "[00:1.00]one digit of seconds",
+            "[00:12.3456]too precise",
+            "[00:12.34 unclosed",
+            "[offset:soon]",
+            "[a b:c]",
+        ];
+        for lyrics in invalid {
+            assert!(validate_lrc(lyrics.as_bytes()).is_err(), "{lyrics}");
+        }
+        assert!(validate_lrc(b"[00:12.34]\xFF\xFE").is_err());
+    }
+}
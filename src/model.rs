@@ -21,6 +21,8 @@ pub enum MusicUploaderError {
     ValidateDirectoryError(Box<ValidateDirectoryError>),
     #[error("Song already exists")]
     SongAlreadyExists,
+    #[error("duplicate of {0}")]
+    DuplicateContent(String),
     #[error("Constraint violation: {0}")]
     ConstraintViolation(String),
     // not user issue
@@ -51,6 +53,36 @@ pub enum DeclareUploadResponse {
     },
 }
 
+#[derive(Serialize, Deserialize)]
+pub enum DeclareArchiveResponse {
+    Complete {
+        entries: Vec<ArchiveEntryReport>,
+    },
+    Incomplete {
+        key: String,
+        declared_size: u32,
+        part_size: u32,
+        received_parts: Vec<u8>,
+    },
+}
+
+/// what happened to one file in an uploaded archive.
+/// detail is where the song was written (or will be once approved), or why it was skipped or rejected.
+#[derive(Serialize, Deserialize)]
+pub struct ArchiveEntryReport {
+    pub name: String,
+    pub status: ArchiveEntryStatus,
+    pub detail: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum ArchiveEntryStatus {
+    Accepted,
+    Quarantined,
+    SkippedDuplicate,
+    Rejected,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct PublicPlaylistResponse {
     pub playlists: Vec<ListedPublicPlaylist>,
@@ -63,6 +95,114 @@ pub struct ListedPublicPlaylist {
     pub num_songs: u32,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct ApiTokenResponse {
+    pub token: String,
+    pub expires: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AuthFailuresResponse {
+    pub failures: Vec<ListedAuthFailure>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListedAuthFailure {
+    pub username: String,
+    pub ip: String,
+    pub reason: String,
+    pub timestamp: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct InviteResponse {
+    pub code: String,
+    pub role: String,
+    pub expires: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct QuarantineResponse {
+    pub uploads: Vec<ListedQuarantinedUpload>,
+}
+
+/// path is where the song goes once approved, relative to upload_dir.
+/// listen is the route admins can play a pending upload from.
+#[derive(Serialize, Deserialize)]
+pub struct ListedQuarantinedUpload {
+    pub id: i64,
+    pub user: String,
+    pub path: String,
+    pub size: u64,
+    pub artist: String,
+    pub album: String,
+    pub title: String,
+    pub status: String,
+    pub reason: String,
+    pub timestamp: i64,
+    pub listen: Option<String>,
+}
+
+/// expires is when the batch stops waiting for files and scans with what it has.
+#[derive(Serialize, Deserialize)]
+pub struct BatchResponse {
+    pub id: String,
+    pub artist: String,
+    pub album: String,
+    pub status: String,
+    pub expires: i64,
+    pub files: Vec<ListedBatchFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListedBatchFile {
+    pub name: String,
+    pub status: String,
+}
+
+/// scan_at is when plex will be asked, null when nothing is queued.
+/// a request's path is relative to the library, empty for the whole library.
+#[derive(Serialize, Deserialize)]
+pub struct ScanQueueResponse {
+    pub scan_at: Option<i64>,
+    pub requests: Vec<ListedScanRequest>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListedScanRequest {
+    pub path: String,
+    pub reason: String,
+    pub requests: u32,
+    pub timestamp: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AliasResponse {
+    pub aliases: Vec<ListedAlias>,
+}
+
+/// artist is only set for album aliases limited to one artist.
+#[derive(Serialize, Deserialize)]
+pub struct ListedAlias {
+    pub kind: String,
+    pub artist: Option<String>,
+    pub alias: String,
+    pub canonical: String,
+    pub created_by: String,
+    pub timestamp: i64,
+}
+
+/// all values are in bytes, limits and remaining are null when unlimited.
+#[derive(Serialize, Deserialize)]
+pub struct QuotaResponse {
+    pub daily_limit: Option<u64>,
+    pub daily_used: u64,
+    pub daily_remaining: Option<u64>,
+    pub lifetime_limit: Option<u64>,
+    pub lifetime_used: u64,
+    pub lifetime_remaining: Option<u64>,
+}
+
 pub fn to_json(obj: &impl Serialize) -> Result<String, MusicUploaderError> {
     serde_json::to_string(obj).map_err(|e| MusicUploaderError::SerdeIssue(Box::new(e)))
 }
@@ -71,36 +211,37 @@ pub fn from_json<'a, T: Deserialize<'a>>(json: &'a str) -> Result<T, MusicUpload
     serde_json::from_str::<'a, T>(json).map_err(|e| MusicUploaderError::SerdeIssue(Box::new(e)))
 }
 
-impl<'r> Responder<'r, 'static> for AlbumSearchResponse {
-    // make this generic if there are more return types
-    fn respond_to(self, request: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
-        let response = to_json(&self).unwrap();
-        Response::build_from(response.respond_to(request)?)
-            .header(ContentType::new("application", "json"))
-            .status(Status::Ok)
-            .ok()
-    }
-}
-
-impl<'r> Responder<'r, 'static> for DeclareUploadResponse {
-    fn respond_to(self, request: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
-        let response = to_json(&self).unwrap();
-        Response::build_from(response.respond_to(request)?)
-            .header(ContentType::new("application", "json"))
-            .status(Status::Ok)
-            .ok()
-    }
+/// implements a json Responder for each of the listed response types.
+macro_rules! json_responder {
+    ($($response_type:ty),+ $(,)?) => {
+        $(
+            impl<'r> Responder<'r, 'static> for $response_type {
+                fn respond_to(self, request: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
+                    let response = to_json(&self).unwrap();
+                    Response::build_from(response.respond_to(request)?)
+                        .header(ContentType::new("application", "json"))
+                        .status(Status::Ok)
+                        .ok()
+                }
+            }
+        )+
+    };
 }
 
-impl<'r> Responder<'r, 'static> for PublicPlaylistResponse {
-    fn respond_to(self, request: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
-        let response = to_json(&self).unwrap();
-        Response::build_from(response.respond_to(request)?)
-            .header(ContentType::new("application", "json"))
-            .status(Status::Ok)
-            .ok()
-    }
-}
+json_responder!(
+    AlbumSearchResponse,
+    DeclareUploadResponse,
+    DeclareArchiveResponse,
+    PublicPlaylistResponse,
+    ApiTokenResponse,
+    AuthFailuresResponse,
+    InviteResponse,
+    QuotaResponse,
+    QuarantineResponse,
+    BatchResponse,
+    ScanQueueResponse,
+    AliasResponse,
+);
 
 impl<'r> Responder<'r, 'static> for MusicUploaderError {
     fn respond_to(self, request: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
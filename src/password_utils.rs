@@ -0,0 +1,78 @@
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use rand::{rngs::OsRng, RngCore};
+use thiserror::Error;
+
+const SECRET_TOKEN_BYTES: usize = 32;
+
+#[derive(Error, Debug)]
+pub enum PasswordError {
+    #[error("failed to hash password: {0}")]
+    HashFailure(String),
+}
+
+/// produces a PHC formatted argon2id hash which is what Secrets.toml expects in `password_hash`.
+/// passwords are trimmed to match how incoming basic auth passwords are compared.
+pub fn hash_password(password: &str) -> Result<String, PasswordError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.trim().as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| PasswordError::HashFailure(e.to_string()))
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    let Ok(parsed_hash) = PasswordHash::new(password_hash) else {
+        println!("attempted to verify against a malformed password hash");
+        return false;
+    };
+    Argon2::default()
+        .verify_password(password.trim().as_bytes(), &parsed_hash)
+        .is_ok()
+}
+
+pub fn is_valid_password_hash(password_hash: &str) -> bool {
+    PasswordHash::new(password_hash).is_ok()
+}
+
+/// random hex string suitable for use as a bearer token.
+pub fn generate_secret_token() -> String {
+    let mut bytes = [0u8; SECRET_TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// tokens are already high entropy so a plain sha256 is enough to avoid storing them directly.
+pub fn hash_secret_token(token: &str) -> String {
+    sha256::digest(token.trim())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hash_verifies() {
+        let hash = hash_password("marley").unwrap();
+        assert!(is_valid_password_hash(&hash));
+        assert!(verify_password("marley", &hash));
+        assert!(verify_password("  marley ", &hash));
+        assert!(!verify_password("mays", &hash));
+    }
+
+    #[test]
+    fn test_secret_tokens_are_unique() {
+        let token = generate_secret_token();
+        assert_eq!(SECRET_TOKEN_BYTES * 2, token.len());
+        assert_ne!(token, generate_secret_token());
+        assert_eq!(hash_secret_token(&token), hash_secret_token(&token));
+    }
+
+    #[test]
+    fn test_plaintext_is_not_a_hash() {
+        assert!(!is_valid_password_hash("marley"));
+        assert!(!verify_password("marley", "marley"));
+    }
+}
@@ -0,0 +1,290 @@
+use std::{ffi::OsStr, path::Path};
+
+use rocket::serde::Deserialize;
+
+use crate::tags::Tags;
+
+/// the layout the server has always used.
+pub const DEFAULT_PATH_TEMPLATE: &str = "{artist}/{album}/{filename}";
+const UNKNOWN_YEAR: &str = "Unknown Year";
+
+/// where songs go inside upload_dir, like `{albumartist}/{year} - {album}/{disc}-{track:02} {title}.{ext}`.
+/// each `/` separated segment becomes a directory, except the last which is the file name.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde", try_from = "String")]
+pub struct PathTemplate {
+    segments: Vec<Vec<TemplatePart>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TemplatePart {
+    Literal(String),
+    Field(TemplateField, Option<usize>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TemplateField {
+    Artist,
+    AlbumArtist,
+    Album,
+    Title,
+    Track,
+    Disc,
+    Year,
+    Ext,
+    FileName,
+}
+
+impl TemplateField {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "artist" => Some(Self::Artist),
+            "albumartist" => Some(Self::AlbumArtist),
+            "album" => Some(Self::Album),
+            "title" => Some(Self::Title),
+            "track" => Some(Self::Track),
+            "disc" => Some(Self::Disc),
+            "year" => Some(Self::Year),
+            "ext" => Some(Self::Ext),
+            "filename" => Some(Self::FileName),
+            _ => None,
+        }
+    }
+
+    fn is_number(&self) -> bool {
+        matches!(self, Self::Track | Self::Disc)
+    }
+
+    /// the artist and album headers are enough to fill these in, the album artist falls back to the artist.
+    fn is_known_without_tags(&self) -> bool {
+        matches!(self, Self::Artist | Self::AlbumArtist | Self::Album)
+    }
+}
+
+/// everything a template can refer to, with fallbacks already applied.
+#[derive(Clone)]
+pub struct SongFields {
+    pub artist: String,
+    pub album_artist: String,
+    pub album: String,
+    pub title: String,
+    pub track: u32,
+    pub disc: u32,
+    pub year: String,
+    pub ext: String,
+    pub file_name: String,
+}
+
+impl SongFields {
+    /// artist and album are whatever the tag_policy settled on, the other fields come from the tags.
+    /// without tags the title is the uploaded file's name, the track 0, the disc 1 and the year "Unknown Year".
+    pub fn new(artist: &str, album: &str, file_name: &str, tags: Option<&Tags>) -> Self {
+        let path = Path::new(file_name);
+        let stem = path
+            .file_stem()
+            .and_then(OsStr::to_str)
+            .unwrap_or(file_name);
+        let ext = path.extension().and_then(OsStr::to_str).unwrap_or_default();
+        let tags = tags.cloned().unwrap_or_default();
+        Self {
+            artist: artist.to_string(),
+            album_artist: tags.album_artist.unwrap_or(artist.to_string()),
+            album: album.to_string(),
+            title: tags.title.unwrap_or(stem.to_string()),
+            track: tags.track.unwrap_or(0),
+            disc: tags.disc.unwrap_or(1),
+            year: tags
+                .year
+                // dates like 2024-06-07 are common in newer tags.
+                .map(|year| year.chars().take(4).collect())
+                .unwrap_or(UNKNOWN_YEAR.to_string()),
+            ext: ext.to_string(),
+            file_name: file_name.to_string(),
+        }
+    }
+
+    fn get(&self, field: TemplateField) -> String {
+        match field {
+            TemplateField::Artist => self.artist.clone(),
+            TemplateField::AlbumArtist => self.album_artist.clone(),
+            TemplateField::Album => self.album.clone(),
+            TemplateField::Title => self.title.clone(),
+            TemplateField::Track => self.track.to_string(),
+            TemplateField::Disc => self.disc.to_string(),
+            TemplateField::Year => self.year.clone(),
+            TemplateField::Ext => self.ext.clone(),
+            TemplateField::FileName => self.file_name.clone(),
+        }
+    }
+}
+
+impl PathTemplate {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let segments = template
+            .split('/')
+            .map(parse_segment)
+            .collect::<Result<Vec<_>, _>>()?;
+        if segments.iter().any(|segment| segment.is_empty()) {
+            return Err(format!("path_template {template} has an empty segment"));
+        }
+        // the file segment has to keep the real extension, it is what valid_extensions checked.
+        let keeps_extension = match segments.last().map(|segment| segment.as_slice()) {
+            Some([.., TemplatePart::Field(TemplateField::FileName, None)]) => true,
+            Some(
+                [.., TemplatePart::Literal(dot), TemplatePart::Field(TemplateField::Ext, None)],
+            ) => dot.ends_with('.'),
+            _ => false,
+        };
+        if !keeps_extension {
+            return Err(format!(
+                "path_template {template} must end in {{filename}} or .{{ext}}"
+            ));
+        }
+        Ok(Self { segments })
+    }
+
+    /// fills in each segment, the last one being the file name.
+    pub fn render(&self, song: &SongFields) -> Vec<String> {
+        self.segments
+            .iter()
+            .map(|segment| segment.iter().map(|part| part.render(song)).collect())
+            .collect()
+    }
+
+    /// like render, but keeps each segment's parts apart and leaves out the ones only tags can fill in.
+    pub fn render_known(&self, song: &SongFields) -> Vec<Vec<Option<String>>> {
+        self.segments
+            .iter()
+            .map(|segment| {
+                segment
+                    .iter()
+                    .map(|part| match part {
+                        TemplatePart::Field(field, _) if !field.is_known_without_tags() => None,
+                        _ => Some(part.render(song)),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+impl TemplatePart {
+    fn render(&self, song: &SongFields) -> String {
+        match self {
+            Self::Literal(literal) => literal.clone(),
+            Self::Field(field, Some(width)) => format!("{:0>width$}", song.get(*field)),
+            Self::Field(field, None) => song.get(*field),
+        }
+    }
+}
+
+impl Default for PathTemplate {
+    fn default() -> Self {
+        Self::parse(DEFAULT_PATH_TEMPLATE).expect("the default path template is valid")
+    }
+}
+
+impl TryFrom<String> for PathTemplate {
+    type Error = String;
+
+    fn try_from(template: String) -> Result<Self, Self::Error> {
+        Self::parse(&template)
+    }
+}
+
+fn parse_segment(segment: &str) -> Result<Vec<TemplatePart>, String> {
+    let mut parts = Vec::new();
+    let mut rest = segment;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(TemplatePart::Literal(rest[..start].to_string()));
+        }
+        let end = rest[start..]
+            .find('}')
+            .ok_or(format!("unclosed {{ in path_template segment {segment}"))?;
+        parts.push(parse_field(&rest[start + 1..start + end])?);
+        rest = &rest[start + end + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(TemplatePart::Literal(rest.to_string()));
+    }
+    Ok(parts)
+}
+
+/// `track` or `track:02`, a width zero pads numbers.
+fn parse_field(field: &str) -> Result<TemplatePart, String> {
+    let (name, width) = match field.split_once(':') {
+        Some((name, width)) => (name, Some(width)),
+        None => (field, None),
+    };
+    let template_field = TemplateField::from_name(name.trim())
+        .ok_or(format!("unknown field {{{field}}} in path_template"))?;
+    let width = match width {
+        None => None,
+        Some(_) if !template_field.is_number() => {
+            return Err(format!(
+                "only track and disc can be padded, not {{{field}}}"
+            ))
+        }
+        Some(width) => Some(
+            width
+                .parse::<usize>()
+                .map_err(|_| format!("invalid padding in {{{field}}}"))?,
+        ),
+    };
+    Ok(TemplatePart::Field(template_field, width))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn build_song(tags: Option<&Tags>) -> SongFields {
+        SongFields::new("Charli XCX", "Brat", "03 Club classics.mp3", tags)
+    }
+
+    #[test]
+    fn test_default_is_the_old_layout() {
+        let rendered = PathTemplate::default().render(&build_song(None));
+        assert_eq!(vec!["Charli XCX", "Brat", "03 Club classics.mp3"], rendered);
+    }
+
+    #[test]
+    fn test_fields_and_padding() {
+        let tags = Tags {
+            album_artist: Some("Charli XCX".to_string()),
+            title: Some("Club classics".to_string()),
+            track: Some(3),
+            year: Some("2024-06-07".to_string()),
+            ..Default::default()
+        };
+        let template =
+            PathTemplate::parse("{albumartist}/{year} - {album}/{disc}-{track:02} {title}.{ext}")
+                .unwrap();
+        assert_eq!(
+            vec!["Charli XCX", "2024 - Brat", "1-03 Club classics.mp3"],
+            template.render(&build_song(Some(&tags)))
+        );
+    }
+
+    #[test]
+    fn test_fallbacks() {
+        let template =
+            PathTemplate::parse("{albumartist}/{year}/{track:02} {title}.{ext}").unwrap();
+        assert_eq!(
+            vec!["Charli XCX", "Unknown Year", "00 03 Club classics.mp3"],
+            template.render(&build_song(None))
+        );
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        assert!(PathTemplate::parse("{artist}/{album}/{title}").is_err());
+        assert!(PathTemplate::parse("{artist}/{album}/{title}{ext}").is_err());
+        assert!(PathTemplate::parse("{artist}//{filename}").is_err());
+        assert!(PathTemplate::parse("{artist}/{genre}/{filename}").is_err());
+        assert!(PathTemplate::parse("{artist:02}/{filename}").is_err());
+        assert!(PathTemplate::parse("{artist/{filename}").is_err());
+        assert!(PathTemplate::parse("{filename}").is_ok());
+    }
+}
@@ -1,14 +1,22 @@
+use deunicode::deunicode;
 use lazy_static::lazy_static;
 use rocket::tokio::fs;
+use rust_fuzzy_search::fuzzy_compare;
 use std::collections::HashSet;
 use std::path::PathBuf;
 use std::{ffi::OsStr, path::Path};
 use thiserror::Error;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
 
-use crate::config::server_config::ServerConfig;
+use crate::alias::apply_aliases;
+use crate::config::server_config::{SanitizeMode, ServerConfig};
+use crate::data::metrics::Metrics;
+use crate::path_template::{PathTemplate, SongFields};
 
 const REPLACEMENT_CHAR: char = '_';
 const DIR_SEGMENT_HASH_LENGTH: usize = 8;
+/// a new folder this similar to an existing one is probably the same artist or album, misspelled.
+const NEAR_DUPLICATE_SIMILARITY: f32 = 0.7;
 lazy_static! {
     static ref LEGAL_CHARS: HashSet<char> = {
         let legal_chars =
@@ -39,21 +47,83 @@ pub enum ValidateDirectoryError {
 async fn validate_or_create_directory(
     base_path: &Path,
     new_dir: &String,
+    mode: SanitizeMode,
 ) -> Result<PathBuf, ValidateDirectoryError> {
-    let path = base_path.join(clean_dir_segment(new_dir));
+    let dir_segment = clean_dir_segment(new_dir, mode);
+    let path = resolve_dir(base_path, &dir_segment);
+    if path != base_path.join(&dir_segment) {
+        println!("using existing directory {path:?} for {dir_segment}");
+    }
     if !path.exists() {
         fs::create_dir(&path)
             .await
             .map_err(|e| ValidateDirectoryError::FailedToCreateDir(e))?;
+        log_near_duplicates(base_path, &dir_segment);
     }
     Ok(path)
 }
 
+/// the folder a cleaned name goes in. names that only differ in case, whitespace, punctuation
+/// or a leading "The" share the folder that already exists, so plex shows them as one.
+fn resolve_dir(base_path: &Path, dir_segment: &str) -> PathBuf {
+    let path = base_path.join(dir_segment);
+    let key = get_match_key(dir_segment);
+    if path.exists() || key.is_empty() {
+        return path;
+    }
+    match list_dirs(base_path)
+        .into_iter()
+        .find(|existing| get_match_key(existing) == key)
+    {
+        Some(existing) => base_path.join(existing),
+        None => path,
+    }
+}
+
+/// names with the same key share a folder, and an alias covers every name with its key.
+pub fn get_match_key(dir_segment: &str) -> String {
+    let lowercase = dir_segment.trim().to_lowercase();
+    let without_article = lowercase.strip_prefix("the ").unwrap_or(&lowercase);
+    without_article
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+fn log_near_duplicates(base_path: &Path, created: &str) {
+    let key = get_match_key(created);
+    for existing in list_dirs(base_path) {
+        if existing != created
+            && fuzzy_compare(&key, &get_match_key(&existing)) >= NEAR_DUPLICATE_SIMILARITY
+        {
+            println!(
+                "created {:?}, which looks like the existing {existing}",
+                base_path.join(created)
+            );
+        }
+    }
+}
+
+/// sorted, so the same folder is picked every time when several match.
+fn list_dirs(base_path: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(base_path) else {
+        return Vec::new();
+    };
+    let mut dirs = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+        .collect::<Vec<_>>();
+    dirs.sort();
+    dirs
+}
+
 async fn validate_file_does_not_exist(
     base_path: &Path,
     file_name: &String,
+    mode: SanitizeMode,
 ) -> Result<PathBuf, ValidateDirectoryError> {
-    let path = base_path.join(clean_file_name(file_name)?);
+    let path = base_path.join(clean_file_name(file_name, mode)?);
     match path.exists() {
         true => Err(ValidateDirectoryError::FileAlreadyExists),
         false => Ok(path),
@@ -83,15 +153,14 @@ fn get_file_stem_extension(file_path: &String) -> Result<(String, String), Valid
     Ok((file_stem, extension))
 }
 
-fn clean_dir_segment(dir_segment: &String) -> String {
+fn clean_dir_segment(dir_segment: &String, mode: SanitizeMode) -> String {
     let mut num_chars = 0;
     let mut num_replaced_chars = 0;
-    let trimmed = dir_segment.trim();
-    let filtered: String = trimmed
+    let filtered: String = prepare(dir_segment, mode)
         .chars()
         .map(|c| {
             num_chars += 1;
-            match LEGAL_CHARS.contains(&c) {
+            match is_legal_char(c, mode) {
                 true => c,
                 false => {
                     num_replaced_chars += 1;
@@ -110,6 +179,42 @@ fn clean_dir_segment(dir_segment: &String) -> String {
     }
 }
 
+fn prepare(dir_segment: &str, mode: SanitizeMode) -> String {
+    let trimmed = dir_segment.trim();
+    match mode {
+        SanitizeMode::Legacy => trimmed.to_string(),
+        // transliteration can start or end a name with a space.
+        SanitizeMode::Transliterate => deunicode(&normalize(trimmed)).trim().to_string(),
+        SanitizeMode::Unicode => normalize(trimmed),
+    }
+}
+
+/// composes accents onto their letters and swaps typographic punctuation for its ascii version,
+/// so `death’s` is filed the same as `death's`.
+fn normalize(string: &str) -> String {
+    string.nfc().map(to_ascii_punctuation).collect()
+}
+
+fn to_ascii_punctuation(c: char) -> char {
+    match c {
+        '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{2032}' | '\u{2039}'
+        | '\u{203A}' => '\'',
+        '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{2033}' | '\u{00AB}'
+        | '\u{00BB}' => '"',
+        '\u{2010}'..='\u{2015}' | '\u{2212}' => '-',
+        '\u{00A0}' | '\u{2000}'..='\u{200A}' | '\u{202F}' | '\u{205F}' | '\u{3000}' => ' ',
+        c => c,
+    }
+}
+
+/// letters, digits and the accents NFC leaves separate are safe in a path on any filesystem.
+fn is_legal_char(c: char, mode: SanitizeMode) -> bool {
+    match mode {
+        SanitizeMode::Unicode if !c.is_ascii() => c.is_alphanumeric() || is_combining_mark(c),
+        _ => LEGAL_CHARS.contains(&c),
+    }
+}
+
 fn get_dir_segment_hash(string: &String) -> String {
     let hash = sha256::digest(string.as_bytes());
     // shorten the hash because a sha256 digest is 256 bits, 32 bytes, 64 hex characters.
@@ -119,13 +224,16 @@ fn get_dir_segment_hash(string: &String) -> String {
         .collect::<String>()
 }
 
-fn clean_file_name(file_name: &String) -> Result<String, ValidateDirectoryError> {
+fn clean_file_name(
+    file_name: &String,
+    mode: SanitizeMode,
+) -> Result<String, ValidateDirectoryError> {
     let dir_escaped_file_name = file_name.replace('/', &REPLACEMENT_CHAR_STR);
     let (stem, extension) = get_file_stem_extension(&dir_escaped_file_name)?;
     Ok(format!(
         "{}.{}",
-        clean_dir_segment(&stem),
-        clean_dir_segment(&extension)
+        clean_dir_segment(&stem, mode),
+        clean_dir_segment(&extension, mode)
     ))
 }
 
@@ -140,19 +248,187 @@ fn validate_file_type(
     }
 }
 
+/// where a song would be written, without touching the filesystem.
+pub fn build_path(
+    server_config: &ServerConfig,
+    song: &SongFields,
+) -> Result<PathBuf, ValidateDirectoryError> {
+    validate_file_type(&server_config.valid_extensions, &song.file_name)?;
+    let song = apply_aliases(server_config, song);
+    let (dirs, file_name) = render_path(&server_config.path_template, &song);
+    Ok(build_dir_path(server_config, &dirs)
+        .join(clean_file_name(&file_name, server_config.sanitize_mode)?))
+}
+
+/// the directory an album's songs go in, going only off of the artist and album.
+/// template fields that come from tags use their fallbacks.
+pub fn build_album_path(server_config: &ServerConfig, artist: &str, album: &str) -> PathBuf {
+    let song = apply_aliases(server_config, &SongFields::new(artist, album, "", None));
+    let (dirs, _) = render_path(&server_config.path_template, &song);
+    build_dir_path(server_config, &dirs)
+}
+
+/// the directory an album's songs are already in. build_album_path can't know tag fields like
+/// {year}, so when its guess doesn't exist the newest song whose directories fit the template is used.
+pub fn find_album_dir(
+    server_config: &ServerConfig,
+    metrics: &Metrics,
+    artist: &str,
+    album: &str,
+) -> Option<PathBuf> {
+    let album_dir = build_album_path(server_config, artist, album);
+    if album_dir.is_dir() {
+        return Some(album_dir);
+    }
+    let song = apply_aliases(server_config, &SongFields::new(artist, album, "", None));
+    let mut segments = server_config.path_template.render_known(&song);
+    segments.pop();
+    let upload_dir = Path::new(&server_config.upload_dir);
+    metrics
+        .get_song_paths()?
+        .iter()
+        .filter_map(|song_path| Path::new(song_path).parent())
+        .find(|dir| {
+            is_album_dir(upload_dir, dir, &segments, server_config.sanitize_mode) && dir.is_dir()
+        })
+        .map(Path::to_path_buf)
+}
+
+/// whether dir could have been rendered from the segments. parts only tags can fill in match anything.
+fn is_album_dir(
+    upload_dir: &Path,
+    dir: &Path,
+    segments: &[Vec<Option<String>>],
+    mode: SanitizeMode,
+) -> bool {
+    let Ok(relative) = dir.strip_prefix(upload_dir) else {
+        return false;
+    };
+    let dir_names = relative
+        .iter()
+        .map(|name| name.to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    dir_names.len() == segments.len()
+        && dir_names.iter().zip(segments).all(|(dir_name, parts)| {
+            match parts.iter().cloned().collect::<Option<String>>() {
+                // the same as resolve_dir would have picked.
+                Some(rendered) => {
+                    get_match_key(dir_name) == get_match_key(&clean_dir_segment(&rendered, mode))
+                }
+                None => {
+                    let pattern = parts
+                        .iter()
+                        .map(|part| part.as_ref().map(|part| get_part_key(part, mode)))
+                        .collect::<Vec<_>>();
+                    matches_key_pattern(&get_plain_key(dir_name), &pattern)
+                }
+            }
+        })
+}
+
+/// a match key that keeps a leading "The", so the keys of a segment's parts add up to the segment's.
+fn get_plain_key(name: &str) -> String {
+    name.to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+/// the key a part of a segment ends up with once the segment is cleaned. parts are too short to
+/// clean on their own, clean_dir_segment would add a hash to most of them.
+fn get_part_key(part: &str, mode: SanitizeMode) -> String {
+    let legal = prepare(part, mode)
+        .chars()
+        .filter(|c| is_legal_char(*c, mode))
+        .collect::<String>();
+    get_plain_key(&legal)
+}
+
+/// None in the pattern matches any run of characters.
+fn matches_key_pattern(key: &str, pattern: &[Option<String>]) -> bool {
+    match pattern.split_first() {
+        None => key.is_empty(),
+        Some((Some(fixed), rest)) => key
+            .strip_prefix(fixed.as_str())
+            .is_some_and(|key| matches_key_pattern(key, rest)),
+        Some((None, rest)) => key
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain([key.len()])
+            .any(|i| matches_key_pattern(&key[i..], rest)),
+    }
+}
+
+fn build_dir_path(server_config: &ServerConfig, dirs: &[String]) -> PathBuf {
+    let mut path = PathBuf::from(&server_config.upload_dir);
+    for dir in dirs {
+        path = resolve_dir(&path, &clean_dir_segment(dir, server_config.sanitize_mode));
+    }
+    path
+}
+
 pub async fn build_and_validate_path(
     server_config: &ServerConfig,
-    artist: &String,
-    album: &String,
-    filename: &String,
+    song: &SongFields,
 ) -> Result<PathBuf, ValidateDirectoryError> {
-    validate_file_type(&server_config.valid_extensions, filename)?;
+    let (dir, file_name) = create_directories(server_config, song).await?;
+    validate_file_does_not_exist(&dir, &file_name, server_config.sanitize_mode).await
+}
+
+/// like build_and_validate_path, but the file is allowed to exist already.
+pub async fn build_and_create_path(
+    server_config: &ServerConfig,
+    song: &SongFields,
+) -> Result<PathBuf, ValidateDirectoryError> {
+    let (dir, file_name) = create_directories(server_config, song).await?;
+    Ok(dir.join(clean_file_name(&file_name, server_config.sanitize_mode)?))
+}
+
+/// returns the song's directory and its uncleaned file name.
+async fn create_directories(
+    server_config: &ServerConfig,
+    song: &SongFields,
+) -> Result<(PathBuf, String), ValidateDirectoryError> {
+    validate_file_type(&server_config.valid_extensions, &song.file_name)?;
     // ultimate path is like
-    // {base_path}/{artist}/{album}/{filename}.{extension}
-    let base_path = Path::new(&server_config.upload_dir);
-    let artist_path = validate_or_create_directory(base_path, artist).await?;
-    let album_path = validate_or_create_directory(&artist_path, album).await?;
-    validate_file_does_not_exist(&album_path, filename).await
+    // {base_path}/{each directory in path_template}/{file name}.{extension}
+    let song = apply_aliases(server_config, song);
+    let (dirs, file_name) = render_path(&server_config.path_template, &song);
+    let mut path = PathBuf::from(&server_config.upload_dir);
+    for dir in dirs {
+        path = validate_or_create_directory(&path, &dir, server_config.sanitize_mode).await?;
+    }
+    Ok((path, file_name))
+}
+
+/// a file that sits next to a song under the same name, like its lyrics.
+/// the song's name was cleaned when it was uploaded, and cleaning it again leaves it as is.
+pub fn build_sidecar_path(
+    song_path: &Path,
+    extension: &str,
+    mode: SanitizeMode,
+) -> Result<PathBuf, ValidateDirectoryError> {
+    let stem = song_path
+        .file_stem()
+        .and_then(OsStr::to_str)
+        .ok_or(ValidateDirectoryError::NoFileExtension)?;
+    let dir = song_path.parent().unwrap_or(Path::new(""));
+    Ok(dir.join(clean_file_name(&format!("{stem}.{extension}"), mode)?))
+}
+
+/// how a song in the library is shown to users, relative to upload_dir.
+pub fn get_library_path(server_config: &ServerConfig, path: &Path) -> String {
+    path.strip_prefix(&server_config.upload_dir)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// splits a rendered template into its directories and the file name, none of them cleaned yet.
+fn render_path(template: &PathTemplate, song: &SongFields) -> (Vec<String>, String) {
+    let mut segments = template.render(song);
+    let file_name = segments.pop().unwrap_or_default();
+    (segments, file_name)
 }
 
 #[cfg(test)]
@@ -165,7 +441,7 @@ mod test {
         for example in examples {
             assert_eq!(
                 example.to_string(),
-                clean_file_name(&example.to_string()).unwrap()
+                clean_file_name(&example.to_string(), SanitizeMode::Legacy).unwrap()
             );
         }
     }
@@ -178,7 +454,10 @@ mod test {
             "Death's Dynamic Shroud",
         ];
         for example in examples {
-            assert_eq!(example.to_string(), clean_dir_segment(&example.to_string()));
+            assert_eq!(
+                example.to_string(),
+                clean_dir_segment(&example.to_string(), SanitizeMode::Legacy)
+            );
         }
     }
 
@@ -186,7 +465,10 @@ mod test {
     fn test_dir_names_that_should_change() {
         let examples = vec!["death’s dynamic shroud"];
         for example in examples {
-            assert_ne!(example.to_string(), clean_dir_segment(&example.to_string()));
+            assert_ne!(
+                example.to_string(),
+                clean_dir_segment(&example.to_string(), SanitizeMode::Legacy)
+            );
         }
     }
 
@@ -194,11 +476,11 @@ mod test {
     fn test_strings_are_stripped() {
         assert_eq!(
             "_test_wav.mp3".to_string(),
-            clean_file_name(&"  .test.wav.mp3   ".to_string()).unwrap()
+            clean_file_name(&"  .test.wav.mp3   ".to_string(), SanitizeMode::Legacy).unwrap()
         );
         assert_eq!(
             "thin_gy".to_string(),
-            clean_dir_segment(&"   thin*gy    ".to_string())
+            clean_dir_segment(&"   thin*gy    ".to_string(), SanitizeMode::Legacy)
         )
     }
 
@@ -206,20 +488,188 @@ mod test {
     fn test_strings_are_escaped() {
         assert_eq!(
             "__urmums_secret files_test_wav.mp3",
-            clean_file_name(&"  ~/urmums/secret files/test.wav.mp3   ".to_string()).unwrap()
+            clean_file_name(
+                &"  ~/urmums/secret files/test.wav.mp3   ".to_string(),
+                SanitizeMode::Legacy
+            )
+            .unwrap()
         );
-        assert_eq!("thin_gy", clean_dir_segment(&"   thin*gy    ".to_string()))
+        assert_eq!(
+            "thin_gy",
+            clean_dir_segment(&"   thin*gy    ".to_string(), SanitizeMode::Legacy)
+        )
     }
 
     #[test]
     fn test_add_hash_to_high_replace_strings() {
         assert_eq!(
             "artist_album___________ec181730.mp3",
-            clean_file_name(&"artist/album/^^^^^^^^^^.mp3".to_string()).unwrap(),
+            clean_file_name(
+                &"artist/album/^^^^^^^^^^.mp3".to_string(),
+                SanitizeMode::Legacy
+            )
+            .unwrap(),
         );
         assert_eq!(
             "artist_album___________3faf76b4.mp3",
-            clean_file_name(&"artist/album/&&&&&&&&&&.mp3".to_string()).unwrap(),
+            clean_file_name(
+                &"artist/album/&&&&&&&&&&.mp3".to_string(),
+                SanitizeMode::Legacy
+            )
+            .unwrap(),
         );
     }
+
+    const MODES: [SanitizeMode; 3] = [
+        SanitizeMode::Legacy,
+        SanitizeMode::Transliterate,
+        SanitizeMode::Unicode,
+    ];
+
+    #[test]
+    fn test_typographic_punctuation_becomes_ascii() {
+        for mode in [SanitizeMode::Transliterate, SanitizeMode::Unicode] {
+            assert_eq!(
+                "death's dynamic shroud",
+                clean_dir_segment(&"death’s dynamic shroud".to_string(), mode)
+            );
+            assert_eq!(
+                "\"Heroes\" - Live",
+                clean_dir_segment(&"“Heroes” – Live".to_string(), mode)
+            );
+        }
+    }
+
+    #[test]
+    fn test_transliterated_names() {
+        let examples = [
+            ("Sigur Ro\u{301}s", "Sigur Ros"),
+            ("Björk", "Bjork"),
+            ("Мумий Тролль", "Mumii Troll'"),
+            ("東京事変", "Dong Jing Shi Bian"),
+        ];
+        for (example, expected) in examples {
+            assert_eq!(
+                expected,
+                clean_dir_segment(&example.to_string(), SanitizeMode::Transliterate)
+            );
+        }
+    }
+
+    #[test]
+    fn test_unicode_names_are_kept() {
+        let examples = [
+            ("Sigur Ro\u{301}s", "Sigur Rós"),
+            ("Björk", "Björk"),
+            ("Мумий Тролль", "Мумий Тролль"),
+            ("東京事変", "東京事変"),
+            ("AC/DC ☆ Live", "AC_DC _ Live"),
+        ];
+        for (example, expected) in examples {
+            assert_eq!(
+                expected,
+                clean_dir_segment(&example.to_string(), SanitizeMode::Unicode)
+            );
+        }
+    }
+
+    #[test]
+    fn test_cleaning_twice_changes_nothing() {
+        for mode in MODES {
+            for name in [
+                "death’s dynamic shroud",
+                "Sigur Ro\u{301}s",
+                "東京事変",
+                "^^^^",
+            ] {
+                let cleaned = clean_dir_segment(&name.to_string(), mode);
+                assert_eq!(cleaned, clean_dir_segment(&cleaned, mode), "{mode:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_sidecars_keep_the_song_name() {
+        for mode in MODES {
+            for file_name in ["a.mp3", "Remind Me.flac", "death’s dynamic shroud.m4a"] {
+                let song_path = Path::new("Artist/Album")
+                    .join(clean_file_name(&file_name.to_string(), mode).unwrap());
+                let lyrics_path = build_sidecar_path(&song_path, "lrc", mode).unwrap();
+                assert_eq!(song_path.with_extension("lrc"), lyrics_path);
+            }
+        }
+    }
+
+    #[test]
+    fn test_match_keys() {
+        for name in [
+            "Charli XCX",
+            "charli xcx",
+            "Charli XCX ",
+            "charli_xcx",
+            "Charli-XCX!",
+        ] {
+            assert_eq!("charlixcx", get_match_key(name), "{name}");
+        }
+        assert_eq!(get_match_key("The Beatles"), get_match_key("beatles"));
+        assert_eq!("theodore", get_match_key("Theodore"));
+        assert_eq!("", get_match_key("!!!"));
+    }
+
+    #[test]
+    fn test_existing_dirs_are_reused() {
+        let base = std::env::temp_dir().join(format!("{:016x}", rand::random::<u64>()));
+        std::fs::create_dir_all(base.join("Charli XCX")).unwrap();
+        std::fs::create_dir_all(base.join("Beatles")).unwrap();
+        assert_eq!(base.join("Charli XCX"), resolve_dir(&base, "charli xcx"));
+        assert_eq!(base.join("Beatles"), resolve_dir(&base, "The Beatles"));
+        assert_eq!(base.join("Charli XCXX"), resolve_dir(&base, "Charli XCXX"));
+        // a name without letters or digits only matches itself.
+        assert_eq!(base.join("!!!"), resolve_dir(&base, "!!!"));
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_album_dirs_with_tag_fields() {
+        let template = PathTemplate::parse("{albumartist}/{year} - {album}/{filename}").unwrap();
+        let mut segments = template.render_known(&SongFields::new("Artist", "Album", "", None));
+        segments.pop();
+        let upload_dir = Path::new("/music");
+        for mode in MODES {
+            let is_match = |dir: &str| is_album_dir(upload_dir, Path::new(dir), &segments, mode);
+            assert!(is_match("/music/Artist/2001 - Album"));
+            assert!(is_match("/music/artist/Unknown Year - Album"));
+            assert!(!is_match("/music/Artist/2001 - Album II"));
+            assert!(!is_match("/music/Other Artist/2001 - Album"));
+            assert!(!is_match("/music/Artist/2001 - Album/CD1"));
+            assert!(!is_match("/elsewhere/Artist/2001 - Album"));
+        }
+    }
+
+    #[test]
+    fn test_key_patterns() {
+        let pattern = [None, Some("album".to_string())];
+        assert!(matches_key_pattern("2001album", &pattern));
+        assert!(matches_key_pattern("album", &pattern));
+        assert!(!matches_key_pattern("2001albumii", &pattern));
+        let pattern = [Some("cd".to_string()), None, Some("x".to_string())];
+        assert!(matches_key_pattern("cd12x", &pattern));
+        assert!(!matches_key_pattern("d12x", &pattern));
+    }
+
+    #[test]
+    fn test_template_values_can_not_add_directories() {
+        let song = SongFields::new("AC/DC", "../..", "../../etc/passwd.mp3", None);
+        let (dirs, file_name) = render_path(&PathTemplate::default(), &song);
+        for mode in MODES {
+            let cleaned = dirs
+                .iter()
+                .map(|dir| clean_dir_segment(dir, mode))
+                .collect::<Vec<_>>();
+            assert!(cleaned
+                .iter()
+                .all(|dir| !dir.contains('/') && !dir.contains('.')));
+            assert!(!clean_file_name(&file_name, mode).unwrap().contains('/'));
+        }
+    }
 }
@@ -0,0 +1,114 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use rocket::tokio::sync::Mutex as AsyncMutex;
+
+use crate::{
+    clients::plex_client::{PlexClient, PlexClientResult},
+    config::server_config::ServerConfig,
+    time_utils::get_now_timestamp,
+};
+
+/// how long the shared user list is trusted before asking plex again.
+const SHARED_USERS_MAX_AGE_SECONDS: i64 = 5 * 60;
+/// an unknown token may belong to someone who was just shared the server,
+/// but don't let unknown tokens hammer plex.tv.
+const SHARED_USERS_MIN_REFRESH_SECONDS: i64 = 60;
+
+/// authenticates plex users that the server has been shared with, using their access token.
+#[derive(Clone)]
+pub struct PlexAuthenticator {
+    /// only held to read or swap the list, never while plex is being asked.
+    shared_users: Arc<Mutex<Option<SharedUsers>>>,
+    /// held while fetching, so logins that need a fresh list wait for one fetch instead of each
+    /// starting their own. logins the cached list can answer never wait on it.
+    refresh: Arc<AsyncMutex<()>>,
+}
+
+struct SharedUsers {
+    fetched_at: i64,
+    /// access token -> plex username
+    usernames: HashMap<String, String>,
+}
+
+impl PlexAuthenticator {
+    pub fn new() -> Self {
+        Self {
+            shared_users: Arc::new(Mutex::new(None)),
+            refresh: Arc::new(AsyncMutex::new(())),
+        }
+    }
+
+    /// returns the plex username that owns the token, if the server is shared with them.
+    pub async fn get_username(&self, server_config: &ServerConfig, token: &str) -> Option<String> {
+        let (username, should_refresh) = self.look_up(token);
+        if !should_refresh {
+            return username;
+        }
+        let _refreshing = self.refresh.lock().await;
+        // another login may have refreshed the list while this one waited.
+        let (username, should_refresh) = self.look_up(token);
+        if !should_refresh {
+            return username;
+        }
+        let now = get_now_timestamp();
+        match Self::fetch_shared_users(server_config).await {
+            Ok(usernames) => {
+                println!("fetched {} shared plex users", usernames.len());
+                let username = usernames.get(token).cloned();
+                *self.lock_shared_users() = Some(SharedUsers {
+                    fetched_at: now,
+                    usernames,
+                });
+                username
+            }
+            Err(e) => {
+                println!("failed to fetch shared plex users, using last known list: {e}");
+                username
+            }
+        }
+    }
+
+    /// the cached username for the token, and whether the list is too old to trust the answer.
+    fn look_up(&self, token: &str) -> (Option<String>, bool) {
+        let shared_users = self.lock_shared_users();
+        let age = shared_users
+            .as_ref()
+            .map(|shared_users| get_now_timestamp() - shared_users.fetched_at);
+        let username = shared_users
+            .as_ref()
+            .and_then(|shared_users| shared_users.usernames.get(token).cloned());
+        let should_refresh = match (age, &username) {
+            (None, _) => true,
+            (Some(age), Some(_)) => age > SHARED_USERS_MAX_AGE_SECONDS,
+            (Some(age), None) => age > SHARED_USERS_MIN_REFRESH_SECONDS,
+        };
+        (username, should_refresh)
+    }
+
+    fn lock_shared_users(&self) -> std::sync::MutexGuard<'_, Option<SharedUsers>> {
+        self.shared_users
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    async fn fetch_shared_users(
+        server_config: &ServerConfig,
+    ) -> PlexClientResult<HashMap<String, String>> {
+        let plex_client = PlexClient::new(
+            &server_config.plex_url,
+            server_config.plex_server_token.clone(),
+        );
+        let server_identifier = plex_client.get_server_identifier().await?;
+        let usernames = plex_client
+            .get_user_info(&server_identifier)
+            .await?
+            .users
+            .into_iter()
+            .map(|user| (user.access_token, user.username))
+            .collect();
+        Ok(usernames)
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    activities::upload::find_song_by_hash,
+    config::server_config::ServerConfig,
+    cover_art::extract_cover_art,
+    data::{
+        metrics::Metrics,
+        operational_data::{
+            NewQuarantinedUpload, OperationalData, QuarantinedUploadItem, QUARANTINE_PENDING,
+        },
+    },
+    data_validation::{write_new_file_atomically, StreamedUpload},
+    model::MusicUploaderError,
+    path_utils::get_library_path,
+    time_utils::get_now_timestamp,
+};
+
+pub fn is_moderated(server_config: &ServerConfig, username: &str) -> bool {
+    server_config
+        .moderated_users
+        .iter()
+        .any(|user| user == username)
+}
+
+/// writes a verified upload to quarantine_dir instead of the library, to be moved to song_path
+/// once an admin approves it. returns the id the admin reviews it under.
+pub fn quarantine_song(
+    server_config: &ServerConfig,
+    upload: StreamedUpload,
+    song_path: &Path,
+    username: &str,
+) -> Result<i64, MusicUploaderError> {
+    let quarantine_dir =
+        server_config
+            .quarantine_dir
+            .as_ref()
+            .ok_or(MusicUploaderError::InternalServerError(
+                "uploads from moderated users need quarantine_dir to be set".to_string(),
+            ))?;
+    fs::create_dir_all(quarantine_dir).map_err(|e| {
+        MusicUploaderError::InternalServerError(format!(
+            "Failed to create quarantine directory: {e}"
+        ))
+    })?;
+    let file_name = song_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    // names are only unique within an album, and the same song may be uploaded again once rejected.
+    let quarantine_path = Path::new(quarantine_dir).join(format!(
+        "{}-{:08x}-{file_name}",
+        get_now_timestamp(),
+        rand::random::<u32>()
+    ));
+    let tags = upload.read_tags().unwrap_or_default();
+    let new_upload = NewQuarantinedUpload {
+        user: username.to_string(),
+        song_path: song_path.to_string_lossy().to_string(),
+        quarantine_path: quarantine_path.to_string_lossy().to_string(),
+        size: upload.size,
+        hash: upload.hash.clone(),
+        artist: tags.artist.unwrap_or_default(),
+        album: tags.album.unwrap_or_default(),
+        title: tags.title.unwrap_or_default(),
+    };
+    upload.persist(&quarantine_path)?;
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let Some(id) = operational_data.quarantine_upload(&new_upload) else {
+        let _ = fs::remove_file(&quarantine_path)
+            .inspect_err(|e| println!("failed to remove {quarantine_path:?}: {e}"));
+        return Err(MusicUploaderError::InternalServerError(
+            "Failed to quarantine upload in db".to_string(),
+        ));
+    };
+    println!(
+        "quarantined upload {id} from {username} for {}",
+        get_library_path(server_config, song_path)
+    );
+    Ok(id)
+}
+
+/// fails with the library path a pending upload with these contents is waiting to go to.
+pub fn check_not_quarantined(
+    server_config: &ServerConfig,
+    hash: &str,
+) -> Result<(), MusicUploaderError> {
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let pending = operational_data
+        .get_pending_quarantined_uploads_by_hash(&hash.to_lowercase())
+        .ok_or(MusicUploaderError::InternalServerError(
+            "failed to look up quarantined uploads by hash".to_string(),
+        ))?;
+    match pending.first() {
+        Some(upload) => Err(MusicUploaderError::DuplicateContent(format!(
+            "{}, waiting for approval",
+            get_library_path(server_config, Path::new(&upload.song_path))
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// moves a pending upload into the library. returns where it was written.
+pub fn approve_upload(
+    server_config: &ServerConfig,
+    id: i64,
+    admin: &str,
+) -> Result<PathBuf, MusicUploaderError> {
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let upload = get_pending_upload(&operational_data, id)?;
+    let song_path = PathBuf::from(&upload.song_path);
+    // the library may have changed while the upload waited.
+    if song_path.exists() {
+        return Err(MusicUploaderError::SongAlreadyExists);
+    }
+    let metrics = Metrics::new(&server_config.server_db_dir);
+    if let Some(existing) = find_song_by_hash(&metrics, &upload.hash)? {
+        return Err(MusicUploaderError::DuplicateContent(get_library_path(
+            server_config,
+            &existing,
+        )));
+    }
+    if let Some(album_dir) = song_path.parent() {
+        fs::create_dir_all(album_dir).map_err(|e| {
+            MusicUploaderError::InternalServerError(format!(
+                "Failed to create album directory: {e}"
+            ))
+        })?;
+    }
+    write_new_file_atomically(&song_path, |file| {
+        io::copy(&mut File::open(&upload.quarantine_path)?, file).map(|_| ())
+    })?;
+    if operational_data.review_quarantined_upload(id, true, "", admin) != Some(true) {
+        // another admin got to it first, leave the library as it was.
+        let _ = fs::remove_file(&song_path);
+        return Err(MusicUploaderError::ConstraintViolation(format!(
+            "upload {id} was already reviewed"
+        )));
+    }
+    remove_quarantined_file(&upload);
+    let _ = metrics.note_upload(&upload.song_path, &upload.user, upload.size, &upload.hash);
+    extract_cover_art(server_config, &song_path);
+    println!("{admin} approved upload {id} from {}", upload.user);
+    Ok(song_path)
+}
+
+/// deletes a pending upload, keeping the reason for the uploader to see.
+pub fn reject_upload(
+    server_config: &ServerConfig,
+    id: i64,
+    admin: &str,
+    reason: &str,
+) -> Result<(), MusicUploaderError> {
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let upload = get_pending_upload(&operational_data, id)?;
+    if operational_data.review_quarantined_upload(id, false, reason, admin) != Some(true) {
+        return Err(MusicUploaderError::ConstraintViolation(format!(
+            "upload {id} was already reviewed"
+        )));
+    }
+    remove_quarantined_file(&upload);
+    remove_empty_album_dirs(server_config, Path::new(&upload.song_path));
+    println!(
+        "{admin} rejected upload {id} from {}: {reason}",
+        upload.user
+    );
+    Ok(())
+}
+
+fn get_pending_upload(
+    operational_data: &OperationalData,
+    id: i64,
+) -> Result<QuarantinedUploadItem, MusicUploaderError> {
+    let upload = operational_data.get_quarantined_upload(id).ok_or(
+        MusicUploaderError::ConstraintViolation(format!("no quarantined upload {id}")),
+    )?;
+    match upload.status == QUARANTINE_PENDING {
+        true => Ok(upload),
+        false => Err(MusicUploaderError::ConstraintViolation(format!(
+            "upload {id} was already {}",
+            upload.status
+        ))),
+    }
+}
+
+fn remove_quarantined_file(upload: &QuarantinedUploadItem) {
+    let _ = fs::remove_file(&upload.quarantine_path).inspect_err(|e| {
+        println!("failed to remove {}: {e}", upload.quarantine_path);
+    });
+}
+
+/// the upload created the song's directories in the library when it was checked.
+/// remove_dir refuses to remove a directory with anything in it, so only empty ones go.
+fn remove_empty_album_dirs(server_config: &ServerConfig, song_path: &Path) {
+    let upload_dir = Path::new(&server_config.upload_dir);
+    let mut dir = song_path.parent();
+    while let Some(current) = dir {
+        if current == upload_dir || !current.starts_with(upload_dir) {
+            break;
+        }
+        if fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
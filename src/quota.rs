@@ -0,0 +1,137 @@
+use rocket::data::ToByteUnit;
+
+use crate::{
+    data::{metrics::Metrics, operational_data::OperationalData},
+    model::{MusicUploaderError, QuotaResponse},
+    time_utils::get_start_of_day_timestamp,
+};
+
+/// upload allowance in bytes, None means unlimited.
+pub struct Quota {
+    pub daily_bytes: Option<u64>,
+    pub lifetime_bytes: Option<u64>,
+}
+
+pub struct QuotaUsage {
+    pub daily_bytes: u64,
+    pub lifetime_bytes: u64,
+}
+
+impl Quota {
+    pub fn from_mb(daily_mb: Option<u32>, lifetime_mb: Option<u32>) -> Self {
+        Self {
+            daily_bytes: daily_mb.map(|mb| mb.megabytes().as_u64()),
+            lifetime_bytes: lifetime_mb.map(|mb| mb.megabytes().as_u64()),
+        }
+    }
+
+    /// uploads waiting in quarantine count as used, or a moderated user could queue up
+    /// any amount for review.
+    pub fn get_usage(
+        metrics: &Metrics,
+        operational_data: &OperationalData,
+        user: &str,
+    ) -> Result<QuotaUsage, MusicUploaderError> {
+        let get_bytes = |since| {
+            Some(
+                metrics.get_uploaded_bytes(user, since)?
+                    + operational_data.get_pending_quarantined_bytes(user, since)?,
+            )
+        };
+        match (get_bytes(get_start_of_day_timestamp()), get_bytes(0)) {
+            (Some(daily_bytes), Some(lifetime_bytes)) => Ok(QuotaUsage {
+                daily_bytes,
+                lifetime_bytes,
+            }),
+            _ => Err(MusicUploaderError::InternalServerError(
+                "Failed to look up uploaded bytes".to_string(),
+            )),
+        }
+    }
+
+    /// errors if accepting incoming_bytes more would put the user over either allowance.
+    pub fn check(&self, usage: &QuotaUsage, incoming_bytes: u64) -> Result<(), MusicUploaderError> {
+        let (daily_remaining, lifetime_remaining) = self.get_remaining(usage);
+        if daily_remaining.is_some_and(|remaining| remaining < incoming_bytes) {
+            return Err(MusicUploaderError::ConstraintViolation(
+                "upload would exceed your daily quota".to_string(),
+            ));
+        }
+        if lifetime_remaining.is_some_and(|remaining| remaining < incoming_bytes) {
+            return Err(MusicUploaderError::ConstraintViolation(
+                "upload would exceed your lifetime quota".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn check_user(
+        &self,
+        metrics: &Metrics,
+        operational_data: &OperationalData,
+        user: &str,
+        incoming_bytes: u64,
+    ) -> Result<(), MusicUploaderError> {
+        if self.daily_bytes.is_none() && self.lifetime_bytes.is_none() {
+            return Ok(());
+        }
+        let usage = Self::get_usage(metrics, operational_data, user)?;
+        self.check(&usage, incoming_bytes)
+    }
+
+    pub fn get_remaining(&self, usage: &QuotaUsage) -> (Option<u64>, Option<u64>) {
+        (
+            self.daily_bytes
+                .map(|limit| limit.saturating_sub(usage.daily_bytes)),
+            self.lifetime_bytes
+                .map(|limit| limit.saturating_sub(usage.lifetime_bytes)),
+        )
+    }
+
+    pub fn build_response(&self, usage: QuotaUsage) -> QuotaResponse {
+        let (daily_remaining, lifetime_remaining) = self.get_remaining(&usage);
+        QuotaResponse {
+            daily_limit: self.daily_bytes,
+            daily_used: usage.daily_bytes,
+            daily_remaining,
+            lifetime_limit: self.lifetime_bytes,
+            lifetime_used: usage.lifetime_bytes,
+            lifetime_remaining,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn build_usage(daily_bytes: u64, lifetime_bytes: u64) -> QuotaUsage {
+        QuotaUsage {
+            daily_bytes,
+            lifetime_bytes,
+        }
+    }
+
+    #[test]
+    fn test_unlimited_quota_accepts_anything() {
+        let quota = Quota::from_mb(None, None);
+        assert!(quota
+            .check(&build_usage(u64::MAX, u64::MAX), u64::MAX)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_quota_rejects_when_exceeded() {
+        let quota = Quota {
+            daily_bytes: Some(100),
+            lifetime_bytes: Some(1000),
+        };
+        assert!(quota.check(&build_usage(50, 50), 50).is_ok());
+        assert!(quota.check(&build_usage(50, 50), 51).is_err());
+        assert!(quota.check(&build_usage(0, 990), 20).is_err());
+        assert_eq!(
+            (Some(0), Some(0)),
+            quota.get_remaining(&build_usage(200, 2000))
+        );
+    }
+}
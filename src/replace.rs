@@ -0,0 +1,214 @@
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    authenticated::Authenticated,
+    config::{secrets_config::Role, server_config::ServerConfig},
+    data::metrics::Metrics,
+    data_validation::StreamedUpload,
+    model::MusicUploaderError,
+    path_utils::get_library_path,
+    time_utils::get_now_timestamp,
+};
+
+/// songs in the same directory with the same name, ignoring the extension.
+/// this is what an upload in replace mode swaps out, so a flac can replace an mp3.
+pub fn find_replaced_songs(server_config: &ServerConfig, song_path: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(stem)) = (song_path.parent(), song_path.file_stem()) else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.file_stem() == Some(stem))
+        .filter(|path| {
+            path.extension()
+                .and_then(OsStr::to_str)
+                .is_some_and(|extension| {
+                    server_config
+                        .valid_extensions
+                        .contains(&extension.to_string())
+                })
+        })
+        .collect()
+}
+
+/// only the user who uploaded a song, or an admin, may replace it.
+/// songs without a known uploader can only be replaced by admins.
+pub fn check_can_replace(
+    server_config: &ServerConfig,
+    metrics: &Metrics,
+    auth: &Authenticated,
+    songs: &[PathBuf],
+) -> Result<(), MusicUploaderError> {
+    get_trash_dir(server_config)?;
+    if auth.role >= Role::Admin {
+        return Ok(());
+    }
+    for song in songs {
+        let uploader = metrics
+            .get_upload(&song.to_string_lossy().to_string())
+            .map(|upload| upload.user);
+        if uploader.as_ref() != Some(&auth.username) {
+            return Err(MusicUploaderError::ConstraintViolation(format!(
+                "only the original uploader or an admin can replace {}",
+                get_library_path(server_config, song)
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// writes a verified upload to song_path. when replace_as is set, the songs it replaces are
+/// moved to the trash once the upload is in place, so the library is never left without the song.
+/// returns the songs that were replaced.
+pub fn persist_song(
+    server_config: &ServerConfig,
+    metrics: &Metrics,
+    upload: StreamedUpload,
+    song_path: &Path,
+    replace_as: Option<&Authenticated>,
+) -> Result<Vec<PathBuf>, MusicUploaderError> {
+    let replaced = match replace_as {
+        Some(auth) => {
+            let replaced = find_replaced_songs(server_config, song_path);
+            check_can_replace(server_config, metrics, auth, &replaced)?;
+            replaced
+        }
+        None => Vec::new(),
+    };
+    if replaced.is_empty() {
+        upload.persist(song_path)?;
+        return Ok(replaced);
+    }
+    let trash_dir = build_trash_dir(server_config)?;
+    // a song with the same extension is overwritten in one rename, so it is copied to the trash first.
+    let overwritten = match replaced.iter().any(|song| song == song_path) {
+        true => Some(copy_to_trash(server_config, &trash_dir, song_path)?),
+        false => None,
+    };
+    let persisted = match overwritten {
+        Some(_) => upload.persist_replacing(song_path),
+        None => upload.persist(song_path),
+    };
+    if let Err(e) = persisted {
+        if let Some(trash_path) = &overwritten {
+            let _ = fs::remove_file(trash_path);
+        }
+        return Err(e);
+    }
+    let mut trashed = overwritten
+        .map(|trash_path| (song_path.to_path_buf(), trash_path))
+        .into_iter()
+        .collect::<Vec<_>>();
+    for song in replaced.iter().filter(|song| *song != song_path) {
+        match move_to_trash(server_config, &trash_dir, song) {
+            Ok(trash_path) => trashed.push((song.clone(), trash_path)),
+            Err(e) => println!("{song:?} was replaced but is still in the library: {e}"),
+        }
+    }
+    let new_path = song_path.to_string_lossy().to_string();
+    for (song, trash_path) in &trashed {
+        println!("replaced {song:?} with {new_path}, the old song is in {trash_path:?}");
+        let _ = metrics.note_replacement(
+            &song.to_string_lossy().to_string(),
+            &trash_path.to_string_lossy().to_string(),
+            &new_path,
+        );
+    }
+    Ok(trashed.into_iter().map(|(song, _)| song).collect())
+}
+
+/// each replacement gets its own directory so nothing in the trash is ever overwritten.
+fn build_trash_dir(server_config: &ServerConfig) -> Result<PathBuf, MusicUploaderError> {
+    Ok(Path::new(get_trash_dir(server_config)?).join(format!(
+        "{}-{:08x}",
+        get_now_timestamp(),
+        rand::random::<u32>()
+    )))
+}
+
+fn get_trash_dir(server_config: &ServerConfig) -> Result<&String, MusicUploaderError> {
+    server_config
+        .trash_dir
+        .as_ref()
+        .ok_or(MusicUploaderError::ConstraintViolation(
+            "replacing songs is disabled, trash_dir is not set".to_string(),
+        ))
+}
+
+fn move_to_trash(
+    server_config: &ServerConfig,
+    trash_dir: &Path,
+    song: &Path,
+) -> Result<PathBuf, MusicUploaderError> {
+    let trash_path = build_trash_path(server_config, trash_dir, song)?;
+    move_file(song, &trash_path).map_err(|e| {
+        MusicUploaderError::InternalServerError(format!("Failed to move {song:?} to trash: {e}"))
+    })?;
+    Ok(trash_path)
+}
+
+fn copy_to_trash(
+    server_config: &ServerConfig,
+    trash_dir: &Path,
+    song: &Path,
+) -> Result<PathBuf, MusicUploaderError> {
+    let trash_path = build_trash_path(server_config, trash_dir, song)?;
+    fs::copy(song, &trash_path).map_err(|e| {
+        MusicUploaderError::InternalServerError(format!("Failed to copy {song:?} to trash: {e}"))
+    })?;
+    Ok(trash_path)
+}
+
+/// keeps the song's place in the library layout inside the trash directory.
+fn build_trash_path(
+    server_config: &ServerConfig,
+    trash_dir: &Path,
+    song: &Path,
+) -> Result<PathBuf, MusicUploaderError> {
+    let trash_path = trash_dir.join(get_library_path(server_config, song));
+    if let Some(parent) = trash_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| {
+            MusicUploaderError::InternalServerError(format!(
+                "Failed to create trash directory: {e}"
+            ))
+        })?;
+    }
+    Ok(trash_path)
+}
+
+/// the trash may be on another filesystem, where renaming doesn't work.
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::rename(from, to).or_else(|_| {
+        fs::copy(from, to)?;
+        fs::remove_file(from)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn test_move_file_keeps_contents() {
+        let dir = env::temp_dir().join(format!("{:016x}", rand::random::<u64>()));
+        fs::create_dir_all(dir.join("trash")).unwrap();
+        let song = dir.join("song.mp3");
+        fs::write(&song, b"128kbps").unwrap();
+        let trash_path = dir.join("trash").join("song.mp3");
+        move_file(&song, &trash_path).unwrap();
+        assert!(!song.exists());
+        move_file(&trash_path, &song).unwrap();
+        assert_eq!(b"128kbps".to_vec(), fs::read(&song).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
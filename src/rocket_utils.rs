@@ -21,3 +21,17 @@ where
         HeaderError::ParsingIssue
     })?)
 }
+
+/// like get_header_value, but a missing header is not an error.
+pub fn get_optional_header_value<T>(
+    headers: &HeaderMap,
+    key: &str,
+) -> Result<Option<T>, HeaderError>
+where
+    T: FromStr,
+{
+    match headers.contains(key) {
+        true => get_header_value(headers, key).map(Some),
+        false => Ok(None),
+    }
+}
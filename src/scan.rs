@@ -0,0 +1,193 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    clients::plex_client::PlexClient,
+    config::server_config::ServerConfig,
+    data::operational_data::OperationalData,
+    model::{ListedScanRequest, MusicUploaderError, ScanQueueResponse},
+    path_utils::get_library_path,
+    time_utils::get_now_timestamp,
+};
+
+/// past this many directories it is cheaper for plex to scan the whole library.
+const MAX_PARTIAL_SCANS: usize = 20;
+const WHOLE_LIBRARY: &str = "";
+
+#[derive(Debug, PartialEq)]
+enum ScanPlan {
+    WholeLibrary,
+    Dirs(Vec<PathBuf>),
+}
+
+/// queues a scan of the directories songs were written to, or of the whole library when there are none.
+/// plex is only asked once the oldest request has waited scan_debounce_seconds, so a burst of
+/// uploads from several people ends up as one scan.
+pub fn request_scan(server_config: &ServerConfig, dirs: &[PathBuf], reason: &str) {
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    if dirs.is_empty() {
+        operational_data.queue_scan(WHOLE_LIBRARY, reason);
+    }
+    for dir in dirs {
+        operational_data.queue_scan(&dir.to_string_lossy(), reason);
+    }
+}
+
+/// queues a scan of the directory a song was written to.
+pub fn request_song_scan(server_config: &ServerConfig, song_path: &Path, reason: &str) {
+    let dirs = song_path
+        .parent()
+        .map(Path::to_path_buf)
+        .into_iter()
+        .collect::<Vec<_>>();
+    request_scan(server_config, &dirs, reason);
+}
+
+/// sends the queued scans to plex once they are due. a failure is only logged,
+/// the songs are in place and the next scan of their directory will find them.
+pub async fn flush_scan_queue(server_config: &ServerConfig) {
+    let Some(plan) = take_due_scans(server_config) else {
+        return;
+    };
+    let plex_client = PlexClient::new(
+        &server_config.plex_url,
+        server_config.plex_server_token.clone(),
+    );
+    let library_id = server_config.plex_music_library_id;
+    match plan {
+        ScanPlan::WholeLibrary => match plex_client.trigger_scan(library_id).await {
+            Ok(_) => println!("plex is scanning the whole library"),
+            Err(e) => println!("failed to trigger a scan of the whole library: {e}"),
+        },
+        ScanPlan::Dirs(dirs) => {
+            for dir in dirs {
+                let plex_path = get_plex_path(server_config, &dir);
+                match plex_client
+                    .trigger_partial_scan(library_id, &plex_path)
+                    .await
+                {
+                    Ok(_) => println!("plex is scanning {plex_path}"),
+                    Err(e) => println!("failed to trigger a scan of {plex_path}: {e}"),
+                }
+            }
+        }
+    }
+}
+
+/// what is waiting to be scanned, and when it will be.
+pub fn get_scan_queue(
+    server_config: &ServerConfig,
+) -> Result<ScanQueueResponse, MusicUploaderError> {
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let requests =
+        operational_data
+            .get_scan_requests()
+            .ok_or(MusicUploaderError::InternalServerError(
+                "Failed to get the scan queue".to_string(),
+            ))?;
+    Ok(ScanQueueResponse {
+        scan_at: requests
+            .first()
+            .map(|oldest| oldest.timestamp + server_config.scan_debounce_seconds as i64),
+        requests: requests
+            .into_iter()
+            .map(|request| ListedScanRequest {
+                path: get_library_path(server_config, Path::new(&request.path)),
+                reason: request.reason,
+                requests: request.requests,
+                timestamp: request.timestamp,
+            })
+            .collect(),
+    })
+}
+
+/// takes everything off the queue once the oldest request is due.
+/// anything queued after this still gets scanned, since its song was written before it was queued.
+fn take_due_scans(server_config: &ServerConfig) -> Option<ScanPlan> {
+    let operational_data = OperationalData::new(&server_config.server_operational_db_dir);
+    let requests = operational_data.get_scan_requests()?;
+    let oldest = requests.first()?;
+    if oldest.timestamp + server_config.scan_debounce_seconds as i64 > get_now_timestamp() {
+        return None;
+    }
+    let count = requests.iter().map(|request| request.requests).sum::<u32>();
+    println!("flushing {count} scan requests");
+    for request in &requests {
+        operational_data.remove_scan_request(&request.path);
+    }
+    Some(plan_scans(
+        requests.into_iter().map(|request| request.path).collect(),
+    ))
+}
+
+/// plex scans a directory recursively, so directories inside other queued ones are dropped.
+fn plan_scans(paths: Vec<String>) -> ScanPlan {
+    if paths.iter().any(|path| path == WHOLE_LIBRARY) {
+        return ScanPlan::WholeLibrary;
+    }
+    let dirs = paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+    let mut outermost = dirs
+        .iter()
+        .filter(|dir| {
+            !dirs
+                .iter()
+                .any(|other| other != *dir && dir.starts_with(other))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    outermost.sort();
+    outermost.dedup();
+    match outermost.len() > MAX_PARTIAL_SCANS {
+        true => ScanPlan::WholeLibrary,
+        false => ScanPlan::Dirs(outermost),
+    }
+}
+
+fn get_plex_path(server_config: &ServerConfig, dir: &Path) -> String {
+    match &server_config.plex_upload_dir {
+        Some(plex_upload_dir) => Path::new(plex_upload_dir)
+            .join(get_library_path(server_config, dir))
+            .to_string_lossy()
+            .to_string(),
+        None => dir.to_string_lossy().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_nested_dirs_are_scanned_once() {
+        let paths = ["/music/Artist/Album", "/music/Artist", "/music/Other/Album"];
+        assert_eq!(
+            ScanPlan::Dirs(vec![
+                PathBuf::from("/music/Artist"),
+                PathBuf::from("/music/Other/Album")
+            ]),
+            plan_scans(paths.iter().map(|path| path.to_string()).collect())
+        );
+        // /music/Artist 2 is not inside /music/Artist.
+        assert_eq!(
+            ScanPlan::Dirs(vec![
+                PathBuf::from("/music/Artist"),
+                PathBuf::from("/music/Artist 2")
+            ]),
+            plan_scans(vec![
+                "/music/Artist 2".to_string(),
+                "/music/Artist".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_whole_library_scans() {
+        assert_eq!(
+            ScanPlan::WholeLibrary,
+            plan_scans(vec!["/music/Artist".to_string(), WHOLE_LIBRARY.to_string()])
+        );
+        let many = (0..=MAX_PARTIAL_SCANS)
+            .map(|i| format!("/music/Artist {i}"))
+            .collect();
+        assert_eq!(ScanPlan::WholeLibrary, plan_scans(many));
+    }
+}
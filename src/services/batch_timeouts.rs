@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+use rocket::tokio;
+
+use crate::{batch::close_expired_batches, config::server_config::ServerConfig};
+
+const POLL_INTERVAL_SECONDS: u64 = 60;
+
+/// scans for batches whose files stopped coming in before they were all uploaded.
+pub(crate) fn start_batch_timeouts(server_config: ServerConfig) {
+    tokio::spawn(watch_batch_timeouts(server_config));
+}
+
+async fn watch_batch_timeouts(server_config: ServerConfig) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(POLL_INTERVAL_SECONDS)).await;
+        close_expired_batches(&server_config);
+    }
+}
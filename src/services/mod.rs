@@ -1 +1,4 @@
+pub mod batch_timeouts;
+pub mod scan_queue;
 pub mod sync_public_playlists;
+pub mod watch_secrets;
@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+use rocket::tokio;
+
+use crate::{config::server_config::ServerConfig, scan::flush_scan_queue};
+
+const POLL_INTERVAL_SECONDS: u64 = 5;
+
+/// asks plex to scan whatever has been queued once it has waited out scan_debounce_seconds.
+pub(crate) fn start_scan_queue(server_config: ServerConfig) {
+    tokio::spawn(watch_scan_queue(server_config));
+}
+
+async fn watch_scan_queue(server_config: ServerConfig) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(POLL_INTERVAL_SECONDS)).await;
+        flush_scan_queue(&server_config).await;
+    }
+}
@@ -387,14 +387,9 @@ impl Job {
 
     async fn get_server_identifier(&self) -> Result<String, String> {
         self.client
-            .get_resources()
+            .get_server_identifier()
             .await
-            .map_err(|e| e.to_string())?
-            .devices
-            .into_iter()
-            .find(|item| item.product == "Plex Media Server")
-            .map(|item| item.client_identifier)
-            .ok_or("could not find da anaswer".to_string())
+            .map_err(|e| e.to_string())
     }
 }
 
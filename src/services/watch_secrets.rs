@@ -0,0 +1,37 @@
+use std::{fs, time::Duration, time::SystemTime};
+
+use rocket::tokio;
+
+use crate::authenticated::{Authenticator, SECRETS_PATH};
+
+const POLL_INTERVAL_SECONDS: u64 = 5;
+
+/// reloads the users whenever Secrets.toml changes on disk.
+pub(crate) fn start_watch_secrets(authenticator: Authenticator) {
+    tokio::spawn(watch_secrets(authenticator));
+}
+
+async fn watch_secrets(authenticator: Authenticator) {
+    let mut last_modified = get_secrets_modified_time();
+    loop {
+        tokio::time::sleep(Duration::from_secs(POLL_INTERVAL_SECONDS)).await;
+        let modified = get_secrets_modified_time();
+        if modified == last_modified {
+            continue;
+        }
+        last_modified = modified;
+        println!("{SECRETS_PATH} changed, reloading users");
+        let reloading_authenticator = authenticator.clone();
+        match tokio::task::spawn_blocking(move || reloading_authenticator.reload()).await {
+            Ok(Ok(_)) => (),
+            Ok(Err(e)) => println!("ERROR: keeping the previous users, failed to reload: {e}"),
+            Err(e) => println!("ERROR: reloading users panicked: {e}"),
+        }
+    }
+}
+
+fn get_secrets_modified_time() -> Option<SystemTime> {
+    fs::metadata(SECRETS_PATH)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
@@ -0,0 +1,752 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::{
+    config::server_config::TagPolicy,
+    content_sniffing::{get_id3_tag_length, read_syncsafe_u32},
+    model::MusicUploaderError,
+};
+
+/// tags are skipped rather than read if they claim to be larger than this, embedded art included.
+const MAX_TAG_BYTES: u64 = 16 * 1024 * 1024;
+/// the mp4 moov box also holds the sample tables, which grow with the length of the song.
+const MAX_MOOV_BYTES: u64 = 64 * 1024 * 1024;
+/// how many ogg pages to look through for the comment header.
+const MAX_OGG_PAGES: usize = 64;
+
+/// the subset of embedded tags we use to place a song in the library.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Tags {
+    pub artist: Option<String>,
+    pub album_artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub track: Option<u32>,
+    pub disc: Option<u32>,
+    pub year: Option<String>,
+}
+
+impl Tags {
+    /// the artist an album should be filed under.
+    pub fn get_directory_artist(&self) -> Option<&String> {
+        self.album_artist.as_ref().or(self.artist.as_ref())
+    }
+
+    fn is_empty(&self) -> bool {
+        self == &Tags::default()
+    }
+
+    fn set_text(&mut self, field: TagField, value: &str) {
+        let value = value.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+        if value.is_empty() {
+            return;
+        }
+        let slot = match field {
+            TagField::Artist => &mut self.artist,
+            TagField::AlbumArtist => &mut self.album_artist,
+            TagField::Album => &mut self.album,
+            TagField::Title => &mut self.title,
+            TagField::Year => &mut self.year,
+            TagField::Track => {
+                self.track = self.track.or(parse_position(value));
+                return;
+            }
+            TagField::Disc => {
+                self.disc = self.disc.or(parse_position(value));
+                return;
+            }
+        };
+        // the first value wins, later duplicates of the same field are ignored.
+        if slot.is_none() {
+            *slot = Some(value.to_string());
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum TagField {
+    Artist,
+    AlbumArtist,
+    Album,
+    Title,
+    Track,
+    Disc,
+    Year,
+}
+
+/// reads whichever tag format the file's container uses. None if there are no usable tags.
+pub fn read_tags<R: Read + Seek>(reader: &mut R) -> Option<Tags> {
+    let mut magic = [0u8; 10];
+    reader.seek(SeekFrom::Start(0)).ok()?;
+    reader.read_exact(&mut magic).ok()?;
+    let tags = match &magic {
+        m if &m[0..3] == b"ID3" => read_id3_tags(reader),
+        m if &m[0..4] == b"fLaC" => read_flac_tags(reader),
+        m if &m[0..4] == b"OggS" => read_ogg_tags(reader),
+        m if &m[4..8] == b"ftyp" => read_mp4_tags(reader),
+        _ => None,
+    }?;
+    match tags.is_empty() {
+        true => None,
+        false => Some(tags),
+    }
+}
+
+/// picks the artist and album a song is filed under from the headers and its tags.
+pub fn resolve_artist_album(
+    policy: TagPolicy,
+    artist: &String,
+    album: &String,
+    tags: Option<&Tags>,
+) -> Result<(String, String), MusicUploaderError> {
+    let from_headers = (artist.clone(), album.clone());
+    let Some(tags) = tags else {
+        return Ok(from_headers);
+    };
+    let mut mismatches = Vec::new();
+    // either artist tag is fine, the header could be the album artist or the track artist.
+    let tag_artists = [tags.album_artist.as_ref(), tags.artist.as_ref()];
+    let tag_artists = tag_artists.into_iter().flatten().collect::<Vec<_>>();
+    if !tag_artists.is_empty() && !tag_artists.iter().any(|tag| is_same_name(artist, tag)) {
+        mismatches.push(format!(
+            "artist \"{artist}\" does not match the tagged artist \"{}\"",
+            tag_artists[0]
+        ));
+    }
+    if let Some(tag_album) = tags.album.as_ref().filter(|tag| !is_same_name(album, tag)) {
+        mismatches.push(format!(
+            "album \"{album}\" does not match the tagged album \"{tag_album}\""
+        ));
+    }
+    if mismatches.is_empty() {
+        return Ok(from_headers);
+    }
+    let mismatches = mismatches.join(", ");
+    match policy {
+        TagPolicy::Warn => {
+            println!("keeping headers even though {mismatches}");
+            Ok(from_headers)
+        }
+        TagPolicy::PreferTags => {
+            println!("using tags because {mismatches}");
+            Ok((
+                tags.get_directory_artist().unwrap_or(artist).clone(),
+                tags.album.as_ref().unwrap_or(album).clone(),
+            ))
+        }
+        TagPolicy::Reject => Err(MusicUploaderError::ConstraintViolation(mismatches)),
+    }
+}
+
+fn is_same_name(header: &str, tag: &str) -> bool {
+    header.trim().to_lowercase() == tag.trim().to_lowercase()
+}
+
+/// "3/12" and "3" both mean 3.
+fn parse_position(value: &str) -> Option<u32> {
+    value.split('/').next()?.trim().parse().ok()
+}
+
+fn read_vec<R: Read>(reader: &mut R, length: u64, max_length: u64) -> Option<Vec<u8>> {
+    if length > max_length {
+        println!("skipping {length} bytes of tags, more than the {max_length} allowed");
+        return None;
+    }
+    let mut bytes = vec![0u8; length as usize];
+    reader.read_exact(&mut bytes).ok()?;
+    Some(bytes)
+}
+
+fn read_u32_be(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+// ID3v2
+
+fn read_id3_tags<R: Read + Seek>(reader: &mut R) -> Option<Tags> {
+    let (body, offset, version, flags) = read_id3_body(reader)?;
+    let mut tags = Tags::default();
+    for frame in Id3Frames::new(&body, offset, version, flags) {
+        let Some(field) = get_id3_field(frame.id) else {
+            continue;
+        };
+        if let Some(text) = decode_id3_text(&frame.data) {
+            tags.set_text(field, &text);
+        }
+    }
+    Some(tags)
+}
+
+pub struct Id3Frame<'a> {
+    pub id: &'a [u8],
+    pub data: Vec<u8>,
+}
+
+/// walks the frames of an ID3v2 tag body, skipping compressed and encrypted frames.
+pub struct Id3Frames<'a> {
+    body: &'a [u8],
+    offset: usize,
+    version: u8,
+    tag_flags: u8,
+}
+
+impl<'a> Id3Frames<'a> {
+    pub fn new(body: &'a [u8], offset: usize, version: u8, tag_flags: u8) -> Self {
+        Self {
+            body,
+            offset,
+            version,
+            tag_flags,
+        }
+    }
+}
+
+impl<'a> Iterator for Id3Frames<'a> {
+    type Item = Id3Frame<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (id_length, header_length) = match self.version {
+                2 => (3, 6),
+                _ => (4, 10),
+            };
+            let header = self.body.get(self.offset..self.offset + header_length)?;
+            let id = &header[..id_length];
+            // padding, the rest of the tag is zeroes.
+            if id[0] == 0 {
+                return None;
+            }
+            let size = match self.version {
+                2 => u32::from_be_bytes([0, header[3], header[4], header[5]]),
+                3 => read_u32_be(&header[4..8]),
+                _ => read_syncsafe_u32(&header[4..8])?,
+            } as usize;
+            let start = self.offset + header_length;
+            let mut data = self.body.get(start..start + size)?;
+            self.offset = start + size;
+            let format_flags = match self.version {
+                2 => 0,
+                _ => header[9],
+            };
+            let (skip, prefix_length, unsynchronised) = match self.version {
+                2 => (false, 0, false),
+                3 => (
+                    format_flags & 0xC0 != 0,
+                    usize::from(format_flags & 0x20 != 0),
+                    false,
+                ),
+                _ => (
+                    format_flags & 0x0C != 0,
+                    usize::from(format_flags & 0x40 != 0)
+                        + 4 * usize::from(format_flags & 0x01 != 0),
+                    format_flags & 0x02 != 0 || self.tag_flags & 0x80 != 0,
+                ),
+            };
+            if skip {
+                continue;
+            }
+            data = data.get(prefix_length..)?;
+            let data = match unsynchronised {
+                true => remove_unsynchronisation(data),
+                false => data.to_vec(),
+            };
+            return Some(Id3Frame { id, data });
+        }
+    }
+}
+
+/// reads the body of a leading ID3v2 tag along with where its frames start, its version and flags.
+pub fn read_id3_body<R: Read + Seek>(reader: &mut R) -> Option<(Vec<u8>, usize, u8, u8)> {
+    let mut header = [0u8; 10];
+    reader.seek(SeekFrom::Start(0)).ok()?;
+    reader.read_exact(&mut header).ok()?;
+    get_id3_tag_length(&header)?;
+    let (version, flags) = (header[3], header[5]);
+    let mut body = read_vec(
+        reader,
+        read_syncsafe_u32(&header[6..10])? as u64,
+        MAX_TAG_BYTES,
+    )?;
+    if flags & 0x80 != 0 && version < 4 {
+        body = remove_unsynchronisation(&body);
+    }
+    let offset = match (flags & 0x40 != 0, version) {
+        (true, 3) => read_u32_be(body.get(0..4)?) as usize + 4,
+        (true, 4) => read_syncsafe_u32(body.get(0..4)?)? as usize,
+        _ => 0,
+    };
+    Some((body, offset, version, flags))
+}
+
+fn get_id3_field(id: &[u8]) -> Option<TagField> {
+    match id {
+        b"TPE1" | b"TP1" => Some(TagField::Artist),
+        b"TPE2" | b"TP2" => Some(TagField::AlbumArtist),
+        b"TALB" | b"TAL" => Some(TagField::Album),
+        b"TIT2" | b"TT2" => Some(TagField::Title),
+        b"TRCK" | b"TRK" => Some(TagField::Track),
+        b"TPOS" | b"TPA" => Some(TagField::Disc),
+        b"TDRC" | b"TYER" | b"TYE" => Some(TagField::Year),
+        _ => None,
+    }
+}
+
+/// an 0xFF followed by an inserted 0x00 goes back to just 0xFF.
+fn remove_unsynchronisation(bytes: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut previous = 0u8;
+    for byte in bytes {
+        if !(previous == 0xFF && *byte == 0x00) {
+            result.push(*byte);
+        }
+        previous = *byte;
+    }
+    result
+}
+
+/// text frames start with an encoding byte, and v2.4 separates multiple values with nulls.
+fn decode_id3_text(data: &[u8]) -> Option<String> {
+    let (encoding, text) = data.split_first()?;
+    let decoded = decode_id3_string(*encoding, text);
+    decoded.split('\0').next().map(|value| value.to_string())
+}
+
+pub fn decode_id3_string(encoding: u8, text: &[u8]) -> String {
+    match encoding {
+        0 => text.iter().map(|byte| *byte as char).collect(),
+        1 | 2 => {
+            let (big_endian, text) = match text {
+                [0xFE, 0xFF, rest @ ..] => (true, rest),
+                [0xFF, 0xFE, rest @ ..] => (false, rest),
+                _ => (encoding == 2, text),
+            };
+            let units = text.chunks_exact(2).map(|pair| match big_endian {
+                true => u16::from_be_bytes([pair[0], pair[1]]),
+                false => u16::from_le_bytes([pair[0], pair[1]]),
+            });
+            char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect()
+        }
+        _ => String::from_utf8_lossy(text).to_string(),
+    }
+}
+
+// Vorbis comments, used by FLAC and OGG
+
+fn read_vorbis_comments(bytes: &[u8], tags: &mut Tags) -> Option<()> {
+    let vendor_length = read_u32_le(bytes.get(0..4)?) as usize;
+    let mut offset = 4 + vendor_length;
+    let count = read_u32_le(bytes.get(offset..offset + 4)?);
+    offset += 4;
+    for _ in 0..count {
+        let length = read_u32_le(bytes.get(offset..offset + 4)?) as usize;
+        offset += 4;
+        let comment = String::from_utf8_lossy(bytes.get(offset..offset + length)?);
+        offset += length;
+        let Some((key, value)) = comment.split_once('=') else {
+            continue;
+        };
+        let field = match key.to_uppercase().as_str() {
+            "ARTIST" => TagField::Artist,
+            "ALBUMARTIST" | "ALBUM ARTIST" | "ALBUM_ARTIST" => TagField::AlbumArtist,
+            "ALBUM" => TagField::Album,
+            "TITLE" => TagField::Title,
+            "TRACKNUMBER" => TagField::Track,
+            "DISCNUMBER" => TagField::Disc,
+            "DATE" | "YEAR" => TagField::Year,
+            _ => continue,
+        };
+        tags.set_text(field, value);
+    }
+    Some(())
+}
+
+/// calls visit with the type and contents of each FLAC metadata block, stopping when it returns false.
+pub fn visit_flac_blocks<R, F>(reader: &mut R, mut visit: F) -> Option<()>
+where
+    R: Read + Seek,
+    F: FnMut(u8, &[u8]) -> bool,
+{
+    reader.seek(SeekFrom::Start(4)).ok()?;
+    loop {
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header).ok()?;
+        let is_last = header[0] & 0x80 != 0;
+        let block_type = header[0] & 0x7F;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+        let keep_going = match block_type {
+            // STREAMINFO, VORBIS_COMMENT and PICTURE
+            0 | 4 | 6 => {
+                let block = read_vec(reader, length, MAX_TAG_BYTES)?;
+                visit(block_type, &block)
+            }
+            _ => {
+                reader.seek(SeekFrom::Current(length as i64)).ok()?;
+                true
+            }
+        };
+        if is_last || !keep_going {
+            return Some(());
+        }
+    }
+}
+
+fn read_flac_tags<R: Read + Seek>(reader: &mut R) -> Option<Tags> {
+    let mut tags = Tags::default();
+    visit_flac_blocks(reader, |block_type, block| {
+        if block_type == 4 {
+            read_vorbis_comments(block, &mut tags);
+            return false;
+        }
+        true
+    })?;
+    Some(tags)
+}
+
+/// returns the first few packets of the first logical stream in an ogg file.
+fn read_ogg_packets<R: Read + Seek>(reader: &mut R, num_packets: usize) -> Option<Vec<Vec<u8>>> {
+    reader.seek(SeekFrom::Start(0)).ok()?;
+    let mut packets: Vec<Vec<u8>> = Vec::new();
+    let mut current = Vec::new();
+    let mut first_serial = None;
+    let mut total_read = 0u64;
+    for _ in 0..MAX_OGG_PAGES {
+        let mut header = [0u8; 27];
+        reader.read_exact(&mut header).ok()?;
+        if &header[0..4] != b"OggS" {
+            return None;
+        }
+        let serial = read_u32_le(&header[14..18]);
+        let mut segment_table = vec![0u8; header[26] as usize];
+        reader.read_exact(&mut segment_table).ok()?;
+        let page_length: u64 = segment_table.iter().map(|length| *length as u64).sum();
+        total_read += page_length;
+        let page = read_vec(reader, page_length, MAX_TAG_BYTES)?;
+        if *first_serial.get_or_insert(serial) != serial || total_read > MAX_TAG_BYTES {
+            continue;
+        }
+        let mut offset = 0;
+        for length in segment_table {
+            current.extend_from_slice(&page[offset..offset + length as usize]);
+            offset += length as usize;
+            // a segment shorter than 255 ends the packet.
+            if length < 255 {
+                packets.push(std::mem::take(&mut current));
+                if packets.len() >= num_packets {
+                    return Some(packets);
+                }
+            }
+        }
+    }
+    None
+}
+
+fn read_ogg_tags<R: Read + Seek>(reader: &mut R) -> Option<Tags> {
+    let packets = read_ogg_packets(reader, 2)?;
+    let comment_packet = packets.get(1)?;
+    let comments = match comment_packet.as_slice() {
+        [0x03, b'v', b'o', b'r', b'b', b'i', b's', rest @ ..] => rest,
+        [b'O', b'p', b'u', b's', b'T', b'a', b'g', b's', rest @ ..] => rest,
+        _ => return None,
+    };
+    let mut tags = Tags::default();
+    read_vorbis_comments(comments, &mut tags)?;
+    Some(tags)
+}
+
+// MP4 atoms
+
+/// finds the moov box, which can be before or after the audio data.
+fn read_mp4_moov<R: Read + Seek>(reader: &mut R) -> Option<Vec<u8>> {
+    let mut offset = 0u64;
+    loop {
+        reader.seek(SeekFrom::Start(offset)).ok()?;
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header).ok()?;
+        let (size, header_length) = match read_u32_be(&header[0..4]) {
+            1 => {
+                let mut large_size = [0u8; 8];
+                reader.read_exact(&mut large_size).ok()?;
+                (u64::from_be_bytes(large_size), 16)
+            }
+            // a zero size box runs to the end of the file.
+            0 => (u64::MAX, 8),
+            size => (size as u64, 8),
+        };
+        if size < header_length {
+            return None;
+        }
+        if &header[4..8] == b"moov" {
+            return read_vec(reader, size - header_length, MAX_MOOV_BYTES);
+        }
+        offset = offset.checked_add(size)?;
+    }
+}
+
+/// iterates over the boxes directly inside a parent box's contents.
+pub fn mp4_children(contents: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let header = contents.get(offset..offset + 8)?;
+        let size = read_u32_be(&header[0..4]) as usize;
+        if size < 8 {
+            return None;
+        }
+        let body = contents.get(offset + 8..offset + size)?;
+        offset += size;
+        Some((&header[4..8], body))
+    })
+}
+
+fn find_mp4_child<'a>(contents: &'a [u8], name: &[u8]) -> Option<&'a [u8]> {
+    mp4_children(contents)
+        .find(|(child_name, _)| *child_name == name)
+        .map(|(_, body)| body)
+}
+
+/// the ilst box holds one child per tag, either under moov/udta/meta or moov/meta.
+pub fn find_mp4_ilst(moov: &[u8]) -> Option<&[u8]> {
+    let meta = find_mp4_child(moov, b"udta")
+        .and_then(|udta| find_mp4_child(udta, b"meta"))
+        .or_else(|| find_mp4_child(moov, b"meta"))?;
+    // meta is a full box, it has 4 bytes of version and flags before its children.
+    find_mp4_child(meta.get(4..)?, b"ilst")
+}
+
+/// the value of an ilst item lives in a data box after 8 bytes of type and locale.
+pub fn get_mp4_item_data(item: &[u8]) -> Option<(u32, &[u8])> {
+    let data = find_mp4_child(item, b"data")?;
+    let data_type = read_u32_be(data.get(0..4)?) & 0x00FF_FFFF;
+    Some((data_type, data.get(8..)?))
+}
+
+pub fn read_mp4_ilst<R: Read + Seek>(reader: &mut R) -> Option<Vec<u8>> {
+    let moov = read_mp4_moov(reader)?;
+    find_mp4_ilst(&moov).map(|ilst| ilst.to_vec())
+}
+
+fn read_mp4_tags<R: Read + Seek>(reader: &mut R) -> Option<Tags> {
+    let ilst = read_mp4_ilst(reader)?;
+    let mut tags = Tags::default();
+    for (name, item) in mp4_children(&ilst) {
+        let Some((_, value)) = get_mp4_item_data(item) else {
+            continue;
+        };
+        let text_field = match name {
+            b"\xA9ART" => Some(TagField::Artist),
+            b"aART" => Some(TagField::AlbumArtist),
+            b"\xA9alb" => Some(TagField::Album),
+            b"\xA9nam" => Some(TagField::Title),
+            b"\xA9day" => Some(TagField::Year),
+            _ => None,
+        };
+        if let Some(field) = text_field {
+            tags.set_text(field, &String::from_utf8_lossy(value));
+            continue;
+        }
+        // track and disc numbers are binary, 2 bytes of padding then the position and total.
+        let position = value
+            .get(2..4)
+            .map(|position| u16::from_be_bytes([position[0], position[1]]) as u32)
+            .filter(|position| *position != 0);
+        match name {
+            b"trkn" => tags.track = tags.track.or(position),
+            b"disk" => tags.disc = tags.disc.or(position),
+            _ => (),
+        }
+    }
+    Some(tags)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn build_id3v3_frame(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut frame = id.to_vec();
+        frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    fn build_id3v3_tag(frames: &[Vec<u8>]) -> Vec<u8> {
+        let body = frames.concat();
+        let size = body.len() as u32;
+        let mut tag = b"ID3\x03\x00\x00".to_vec();
+        tag.extend(
+            (0..4)
+                .rev()
+                .map(|shift| ((size >> (shift * 7)) & 0x7F) as u8),
+        );
+        tag.extend_from_slice(&body);
+        tag.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x64]);
+        tag
+    }
+
+    fn build_mp4_box(name: &[u8], contents: &[u8]) -> Vec<u8> {
+        let mut mp4_box = ((contents.len() + 8) as u32).to_be_bytes().to_vec();
+        mp4_box.extend_from_slice(name);
+        mp4_box.extend_from_slice(contents);
+        mp4_box
+    }
+
+    fn build_mp4(items: &[Vec<u8>]) -> Vec<u8> {
+        let ilst = build_mp4_box(b"ilst", &items.concat());
+        let meta = build_mp4_box(b"meta", &[vec![0, 0, 0, 0], ilst].concat());
+        let moov = build_mp4_box(b"moov", &build_mp4_box(b"udta", &meta));
+        let ftyp = build_mp4_box(b"ftyp", b"M4A \x00\x00\x02\x00M4A mp42");
+        // moov after the audio, like most encoders write it.
+        [ftyp, build_mp4_box(b"mdat", &[0u8; 32]), moov].concat()
+    }
+
+    fn build_mp4_item(name: &[u8], data_type: u32, value: &[u8]) -> Vec<u8> {
+        let data = [&data_type.to_be_bytes()[..], &[0, 0, 0, 0], value].concat();
+        build_mp4_box(name, &build_mp4_box(b"data", &data))
+    }
+
+    fn build_vorbis_comments(comments: &[&str]) -> Vec<u8> {
+        let mut bytes = 6u32.to_le_bytes().to_vec();
+        bytes.extend_from_slice(b"vendor");
+        bytes.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            bytes.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(comment.as_bytes());
+        }
+        bytes
+    }
+
+    fn build_ogg_page(serial: u32, packet: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS\x00\x02".to_vec();
+        page.extend_from_slice(&[0; 8]);
+        page.extend_from_slice(&serial.to_le_bytes());
+        page.extend_from_slice(&[0; 8]);
+        let mut segments = vec![255u8; packet.len() / 255];
+        segments.push((packet.len() % 255) as u8);
+        page.push(segments.len() as u8);
+        page.extend_from_slice(&segments);
+        page.extend_from_slice(packet);
+        page
+    }
+
+    fn read(bytes: Vec<u8>) -> Option<Tags> {
+        read_tags(&mut Cursor::new(bytes))
+    }
+
+    #[test]
+    fn test_id3v3_text_frames() {
+        let tag = build_id3v3_tag(&[
+            build_id3v3_frame(b"TPE1", b"\x00Charli XCX"),
+            // utf-16 with a byte order mark
+            build_id3v3_frame(b"TALB", b"\x01\xFF\xFEB\x00r\x00a\x00t\x00"),
+            build_id3v3_frame(b"TRCK", b"\x033/15"),
+            build_id3v3_frame(b"TYER", b"\x002024"),
+        ]);
+        let tags = read(tag).unwrap();
+        assert_eq!(Some("Charli XCX".to_string()), tags.artist);
+        assert_eq!(Some("Brat".to_string()), tags.album);
+        assert_eq!(Some(3), tags.track);
+        assert_eq!(Some("2024".to_string()), tags.year);
+        assert_eq!(Some(&"Charli XCX".to_string()), tags.get_directory_artist());
+    }
+
+    #[test]
+    fn test_flac_vorbis_comments() {
+        let comments = build_vorbis_comments(&[
+            "artist=Boards of Canada",
+            "ALBUMARTIST=Boards of Canada",
+            "ALBUM=Geogaddi",
+            "TRACKNUMBER=2",
+            "DISCNUMBER=1/1",
+        ]);
+        let mut flac = b"fLaC\x00\x00\x00\x22".to_vec();
+        flac.extend_from_slice(&[0u8; 34]);
+        flac.push(0x84);
+        flac.extend_from_slice(&(comments.len() as u32).to_be_bytes()[1..]);
+        flac.extend_from_slice(&comments);
+        let tags = read(flac).unwrap();
+        assert_eq!(Some("Boards of Canada".to_string()), tags.album_artist);
+        assert_eq!(Some("Geogaddi".to_string()), tags.album);
+        assert_eq!(Some(2), tags.track);
+        assert_eq!(Some(1), tags.disc);
+    }
+
+    #[test]
+    fn test_ogg_vorbis_comments() {
+        let identification = b"\x01vorbis\x00\x00\x00\x00".to_vec();
+        let comments = [
+            b"\x03vorbis".to_vec(),
+            build_vorbis_comments(&["TITLE=Roygbiv", "ARTIST=Boards of Canada"]),
+        ]
+        .concat();
+        let ogg = [
+            build_ogg_page(7, &identification),
+            build_ogg_page(7, &comments),
+        ]
+        .concat();
+        let tags = read(ogg).unwrap();
+        assert_eq!(Some("Roygbiv".to_string()), tags.title);
+        assert_eq!(Some("Boards of Canada".to_string()), tags.artist);
+    }
+
+    #[test]
+    fn test_mp4_ilst() {
+        let mp4 = build_mp4(&[
+            build_mp4_item(b"\xA9ART", 1, "Beyoncé".as_bytes()),
+            build_mp4_item(b"\xA9alb", 1, b"Renaissance"),
+            build_mp4_item(b"trkn", 0, &[0, 0, 0, 4, 0, 16, 0, 0]),
+        ]);
+        let tags = read(mp4).unwrap();
+        assert_eq!(Some("Beyoncé".to_string()), tags.artist);
+        assert_eq!(Some("Renaissance".to_string()), tags.album);
+        assert_eq!(Some(4), tags.track);
+    }
+
+    #[test]
+    fn test_untagged_files_have_no_tags() {
+        assert_eq!(
+            None,
+            read(vec![0xFF, 0xFB, 0x90, 0x64, 0, 0, 0, 0, 0, 0, 0])
+        );
+        assert_eq!(None, read(build_id3v3_tag(&[])));
+        assert_eq!(None, read(b"short".to_vec()));
+    }
+
+    #[test]
+    fn test_tag_policies() {
+        let tags = Tags {
+            artist: Some("Charli XCX".to_string()),
+            album: Some("Brat".to_string()),
+            ..Default::default()
+        };
+        let artist = "charli xcx ".to_string();
+        let typo = "Brta".to_string();
+        let album = "brat".to_string();
+        let resolve =
+            |policy, album: &String| resolve_artist_album(policy, &artist, album, Some(&tags));
+        // differences in case and whitespace are not mismatches.
+        assert_eq!(
+            (artist.clone(), album.clone()),
+            resolve(TagPolicy::Reject, &album).unwrap()
+        );
+        assert_eq!(
+            (artist.clone(), typo.clone()),
+            resolve(TagPolicy::Warn, &typo).unwrap()
+        );
+        assert_eq!(
+            ("Charli XCX".to_string(), "Brat".to_string()),
+            resolve(TagPolicy::PreferTags, &typo).unwrap()
+        );
+        assert!(resolve(TagPolicy::Reject, &typo).is_err());
+        assert_eq!(
+            (artist.clone(), typo.clone()),
+            resolve_artist_album(TagPolicy::Reject, &artist, &typo, None).unwrap()
+        );
+    }
+}
@@ -1,5 +1,11 @@
-use time::OffsetDateTime;
+use time::{OffsetDateTime, Time};
 
 pub fn get_now_timestamp() -> i64 {
     OffsetDateTime::now_utc().unix_timestamp()
 }
+
+pub fn get_start_of_day_timestamp() -> i64 {
+    OffsetDateTime::now_utc()
+        .replace_time(Time::MIDNIGHT)
+        .unix_timestamp()
+}